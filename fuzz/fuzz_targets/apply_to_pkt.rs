@@ -0,0 +1,57 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+#![no_main]
+
+//! Rewrites a parsed packet with arbitrary addresses and ports, covering
+//! contexts the table would not produce.
+
+use libfuzzer_sys::fuzz_target;
+use rustapp_fuzz::{packet, HostPkt, Input};
+
+fuzz_target!(|data: &[u8]| {
+    // New source/destination address and ports of the packet, then of
+    // the packet quoted in an ICMP error
+    let (rewrite, rest) = match data.split_at_checked(24) {
+        Some(split) => split,
+        None => return,
+    };
+    let input = match Input::parse(rest) {
+        Some(input) => input,
+        None => return,
+    };
+    let mut pkt = input.to_pkt();
+
+    let mut ctx = match unsafe { packet::from_pkt(pkt.as_ptr()) } {
+        Some(ctx) => ctx,
+        None => return,
+    };
+
+    let word = |off: usize| u16::from_be_bytes([rewrite[off], rewrite[off + 1]]);
+    ctx.ip_hdr.src.copy_from_slice(&rewrite[0..4]);
+    ctx.ip_hdr.dst.copy_from_slice(&rewrite[4..8]);
+    ctx.src_port = word(8);
+    ctx.dst_port = word(10);
+    if let Some(inner) = ctx.inner.as_mut() {
+        inner.src.copy_from_slice(&rewrite[12..16]);
+        inner.dst.copy_from_slice(&rewrite[16..20]);
+        inner.src_port = word(20);
+        inner.dst_port = word(22);
+    }
+    ctx.needs_update = true;
+
+    // Same bytes in one buffer, the rewrite must not depend on the split
+    let bytes = pkt.bytes();
+    let mut whole = HostPkt::new(&[&bytes], input.iface());
+
+    unsafe { packet::apply_to_pkt(&ctx, pkt.as_ptr()) };
+    unsafe { packet::apply_to_pkt(&ctx, whole.as_ptr()) };
+    assert_eq!(pkt.bytes(), whole.bytes());
+
+    // The headers stay parsable and carry the new addresses
+    let after =
+        unsafe { packet::from_pkt(pkt.as_ptr()) }.expect("rewritten packet no longer parses");
+    assert_eq!(after.ip_hdr.src, ctx.ip_hdr.src);
+    assert_eq!(after.ip_hdr.dst, ctx.ip_hdr.dst);
+});
@@ -0,0 +1,38 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustapp_fuzz::{packet, HostPkt, Input};
+
+fuzz_target!(|data: &[u8]| {
+    let input = match Input::parse(data) {
+        Some(input) => input,
+        None => return,
+    };
+    let mut pkt = input.to_pkt();
+
+    let ctx = unsafe { packet::from_pkt(pkt.as_ptr()) };
+
+    // Same bytes in one buffer, parsing must not depend on the split
+    let bytes = pkt.bytes();
+    let mut whole = HostPkt::new(&[&bytes], input.iface());
+    let expected = unsafe { packet::from_pkt(whole.as_ptr()) };
+
+    match (ctx, expected) {
+        (Some(ctx), Some(expected)) => {
+            assert_eq!(ctx.iface, input.iface());
+            assert!(!ctx.needs_update);
+            assert_eq!(ctx.ip_hdr.src, expected.ip_hdr.src);
+            assert_eq!(ctx.ip_hdr.dst, expected.ip_hdr.dst);
+            assert_eq!(ctx.src_port, expected.src_port);
+            assert_eq!(ctx.dst_port, expected.dst_port);
+            assert_eq!(ctx.tcp_flags, expected.tcp_flags);
+            assert_eq!(ctx.inner.is_some(), expected.inner.is_some());
+        }
+        (None, None) => {}
+        _ => panic!("parsing depends on the fragment layout"),
+    }
+});
@@ -0,0 +1,86 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+#![no_main]
+
+//! Runs a sequence of packets through one table, so earlier packets can
+//! create the mappings later ones hit.
+
+use libfuzzer_sys::fuzz_target;
+use nat_core::clock::MockClock;
+use nat_core::entry::Protocol;
+use nat_core::forward::PortForward;
+use nat_core::testing::{LAN, WAN};
+use nat_core::{NatConfig, NatTable};
+use rustapp_fuzz::{packet, Input};
+
+const CAPACITY: usize = 16;
+
+fn table(clock: &MockClock) -> NatTable<&MockClock, CAPACITY> {
+    let config = NatConfig {
+        internal_network: [192, 168, 4, 0],
+        internal_netmask: [255, 255, 255, 0],
+        external_ip: [203, 0, 113, 1],
+        internal_iface: LAN,
+        external_iface: WAN,
+        dmz_host: Some([192, 168, 4, 9]),
+        ..NatConfig::default()
+    };
+    let mut table = NatTable::new(config, clock);
+
+    let _ = table.add_port_forward(PortForward {
+        protocol: Protocol::Tcp,
+        external_start: 8080,
+        external_end: 8090,
+        internal_ip: [192, 168, 4, 2],
+        internal_port: 80,
+        lease: None,
+    });
+    table
+}
+
+fuzz_target!(|data: &[u8]| {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+
+    // Records of `[length][advance seconds][input]`
+    let mut rest = data;
+    while let [len, advance, tail @ ..] = rest {
+        let (record, tail) = tail.split_at((*len as usize).min(tail.len()));
+        rest = tail;
+        clock.advance(*advance as u64 * 1000);
+
+        let input = match Input::parse(record) {
+            Some(input) => input,
+            None => continue,
+        };
+        let mut pkt = input.to_pkt();
+
+        let mut ctx = match unsafe { packet::from_pkt(pkt.as_ptr()) } {
+            Some(ctx) => ctx,
+            None => continue,
+        };
+        let result = if input.wan {
+            table.translate_inbound(&mut ctx)
+        } else {
+            table.translate_outbound(&mut ctx)
+        };
+        if result.is_err() {
+            continue;
+        }
+
+        unsafe { packet::apply_to_pkt(&ctx, pkt.as_ptr()) };
+
+        let after =
+            unsafe { packet::from_pkt(pkt.as_ptr()) }.expect("translated packet no longer parses");
+        assert_eq!(after.ip_hdr.src, ctx.ip_hdr.src);
+        assert_eq!(after.ip_hdr.dst, ctx.ip_hdr.dst);
+        if !ctx.iface.is_null() {
+            assert_eq!(pkt.iface(), ctx.iface);
+        }
+    }
+
+    table.housekeeping();
+    assert!(table.len() <= CAPACITY);
+});
@@ -0,0 +1,159 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+//! Host stand-in for Zephyr packets, shared by the fuzz targets.
+//!
+//! The firmware glue in `src/packet.rs` and `src/netbuf.rs` is compiled
+//! as is against the `NetPkt`/`NetBuf` definitions of `src/ffi.rs`.
+//! Packets are built here from `#[repr(C)]` mirrors of those structs,
+//! with every fragment in its own exactly sized allocation so the
+//! sanitizer catches any read past `NetBuf::len`.
+
+#[path = "../../src/ffi.rs"]
+pub mod ffi;
+#[path = "../../src/netbuf.rs"]
+pub mod netbuf;
+#[path = "../../src/packet.rs"]
+pub mod packet;
+
+use core::mem::size_of;
+use core::ptr;
+use ffi::{NetBuf, NetIf, NetPkt};
+use nat_core::testing::{LAN, WAN};
+
+/// Layout of `struct net_buf`
+#[repr(C)]
+struct HostBuf {
+    node: *mut u8,
+    frags: *mut HostBuf,
+    r#ref: u8,
+    flags: u8,
+    pool_id: u8,
+    user_data_size: u8,
+    data: *mut u8,
+    len: u16,
+    size: u16,
+    buf: *mut u8,
+}
+
+/// Layout of the start of `struct net_pkt`
+#[repr(C)]
+struct HostPktHdr {
+    fifo: isize,
+    slab: *mut u8,
+    frags: *mut HostBuf,
+    cursor: [*mut u8; 2],
+    context: *mut u8,
+    iface: *mut NetIf,
+}
+
+const _: () = assert!(size_of::<HostBuf>() == size_of::<NetBuf>());
+const _: () = assert!(size_of::<HostPktHdr>() == size_of::<NetPkt>());
+
+/// Packet whose data is spread over a chain of buffers
+pub struct HostPkt {
+    pkt: Box<HostPktHdr>,
+    bufs: Vec<HostBuf>,
+    data: Vec<Box<[u8]>>,
+}
+
+impl HostPkt {
+    pub fn new(fragments: &[&[u8]], iface: *mut NetIf) -> Self {
+        let mut data: Vec<Box<[u8]>> = fragments.iter().map(|f| Box::from(*f)).collect();
+        let mut bufs: Vec<HostBuf> = data
+            .iter_mut()
+            .map(|d| HostBuf {
+                node: ptr::null_mut(),
+                frags: ptr::null_mut(),
+                r#ref: 1,
+                flags: 0,
+                pool_id: 0,
+                user_data_size: 0,
+                data: d.as_mut_ptr(),
+                len: d.len() as u16,
+                size: d.len() as u16,
+                buf: d.as_mut_ptr(),
+            })
+            .collect();
+
+        // The vector is never resized, so the chain stays valid
+        let first = bufs.as_mut_ptr();
+        for i in 1..bufs.len() {
+            unsafe { (*first.add(i - 1)).frags = first.add(i) };
+        }
+
+        let pkt = Box::new(HostPktHdr {
+            fifo: 0,
+            slab: ptr::null_mut(),
+            frags: if bufs.is_empty() {
+                ptr::null_mut()
+            } else {
+                first
+            },
+            cursor: [ptr::null_mut(); 2],
+            context: ptr::null_mut(),
+            iface,
+        });
+
+        Self { pkt, bufs, data }
+    }
+
+    pub fn as_ptr(&mut self) -> *mut NetPkt {
+        (&mut *self.pkt as *mut HostPktHdr).cast()
+    }
+
+    pub fn iface(&self) -> *mut NetIf {
+        self.pkt.iface
+    }
+
+    /// Packet bytes across all fragments
+    pub fn bytes(&self) -> Vec<u8> {
+        debug_assert_eq!(self.bufs.len(), self.data.len());
+        self.data.concat()
+    }
+}
+
+/// Fuzz input: `[flags][fragment sizes][packet bytes]`
+///
+/// Bit 0 of `flags` makes the packet WAN ingress, bits 1-2 give the
+/// number of leading fragments whose sizes follow, one byte each. The
+/// rest of the packet goes in the last fragment.
+pub struct Input<'a> {
+    pub wan: bool,
+    pub fragments: Vec<&'a [u8]>,
+}
+
+impl<'a> Input<'a> {
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let (&flags, rest) = data.split_first()?;
+        let splits = ((flags >> 1) & 0x03) as usize;
+        let sizes = rest.get(..splits)?;
+        let mut bytes = &rest[splits..];
+
+        let mut fragments = Vec::with_capacity(splits + 1);
+        for &size in sizes {
+            let (head, tail) = bytes.split_at((size as usize).min(bytes.len()));
+            fragments.push(head);
+            bytes = tail;
+        }
+        fragments.push(bytes);
+
+        Some(Self {
+            wan: flags & 0x01 != 0,
+            fragments,
+        })
+    }
+
+    pub fn iface(&self) -> *mut NetIf {
+        if self.wan {
+            WAN
+        } else {
+            LAN
+        }
+    }
+
+    pub fn to_pkt(&self) -> HostPkt {
+        HostPkt::new(&self.fragments, self.iface())
+    }
+}
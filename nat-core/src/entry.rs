@@ -0,0 +1,353 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+use super::index::{endpoint_hash, inbound_hash, outbound_hash, port_hash};
+use crate::packet::{TCP_ACK, TCP_FIN, TCP_RST, TCP_SYN};
+use crate::NetIf;
+
+/// IP protocol types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+    Icmp,
+    /// PPTP enhanced GRE, keyed on call IDs
+    Gre,
+    /// IPsec ESP, keyed on SPIs
+    Esp,
+    /// Any other IP protocol, translated by address only (GRE other than
+    /// PPTP, SCTP, IGMP, UDP-Lite, ...)
+    Other(u8),
+}
+
+impl Protocol {
+    /// Protocols with ports or query identifiers, the ones port forwards
+    /// and mapping requests may name
+    pub fn from_u8(val: u8) -> Option<Self> {
+        match val {
+            6 => Some(Protocol::Tcp),
+            17 => Some(Protocol::Udp),
+            1 => Some(Protocol::Icmp),
+            _ => None,
+        }
+    }
+
+    /// Protocol of an IP packet, GRE is only told apart by its header
+    pub fn from_ip(val: u8) -> Self {
+        match val {
+            50 => Protocol::Esp,
+            _ => Self::from_u8(val).unwrap_or(Protocol::Other(val)),
+        }
+    }
+
+    /// IP protocol number
+    pub fn number(self) -> u8 {
+        match self {
+            Protocol::Tcp => 6,
+            Protocol::Udp => 17,
+            Protocol::Icmp => 1,
+            Protocol::Gre => 47,
+            Protocol::Esp => 50,
+            Protocol::Other(val) => val,
+        }
+    }
+
+    /// Mapped by address only, one internal host per remote
+    pub fn is_address_only(self) -> bool {
+        matches!(self, Protocol::Other(_))
+    }
+}
+
+/// TCP connection state, as seen from the flags passing through the NAT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpState {
+    /// SYN seen, waiting for SYN+ACK
+    SynSent,
+    Established,
+    /// One side sent FIN (`by_lan` tells which)
+    FinWait {
+        by_lan: bool,
+    },
+    /// Both sides sent FIN
+    TimeWait,
+    /// Reset by either side
+    Closed,
+}
+
+impl TcpState {
+    /// State of a session created by a segment
+    pub fn initial(flags: u8) -> Self {
+        if flags & TCP_RST != 0 {
+            TcpState::Closed
+        } else if flags & (TCP_SYN | TCP_ACK) == TCP_SYN {
+            TcpState::SynSent
+        } else {
+            // Picked up mid-stream (e.g. after a reboot)
+            TcpState::Established
+        }
+    }
+}
+
+/// NAT connection entry
+#[derive(Debug, Clone, Copy)]
+pub struct NatEntry {
+    /// Internal (LAN) IP address
+    pub internal_ip: [u8; 4],
+
+    /// Internal (LAN) port
+    pub internal_port: u16,
+
+    /// External (WAN) IP address (our public IP)
+    pub external_ip: [u8; 4],
+
+    /// External (WAN) port (mapped port)
+    pub external_port: u16,
+
+    /// Remote IP address (destination on internet)
+    pub remote_ip: [u8; 4],
+
+    /// Remote port
+    pub remote_port: u16,
+
+    /// SPI of the ESP sent to the remote (ESP entries only)
+    pub spi_out: u32,
+
+    /// SPI of the ESP received from the remote, 0 until learned (ESP
+    /// entries only)
+    pub spi_in: u32,
+
+    /// Protocol (TCP/UDP/ICMP)
+    pub protocol: Protocol,
+
+    /// TCP connection state (TCP entries only)
+    pub tcp_state: TcpState,
+
+    /// Creation timestamp (ms since boot)
+    pub created: u64,
+
+    /// Last activity timestamp (ms since boot)
+    pub last_activity: u64,
+
+    /// Entry is in use
+    pub in_use: bool,
+
+    /// Internal (LAN) interface pointer
+    pub internal_iface: *mut NetIf,
+
+    /// External (WAN) interface pointer
+    pub external_iface: *mut NetIf,
+}
+
+impl Default for NatEntry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NatEntry {
+    pub const fn new() -> Self {
+        Self {
+            internal_ip: [0; 4],
+            internal_port: 0,
+            external_ip: [0; 4],
+            external_port: 0,
+            remote_ip: [0; 4],
+            remote_port: 0,
+            spi_out: 0,
+            spi_in: 0,
+            protocol: Protocol::Tcp,
+            tcp_state: TcpState::Closed,
+            created: 0,
+            last_activity: 0,
+            in_use: false,
+            internal_iface: core::ptr::null_mut(),
+            external_iface: core::ptr::null_mut(),
+        }
+    }
+
+    /// Check if entry matches outbound packet
+    pub fn matches_outbound(
+        &self,
+        src_ip: &[u8; 4],
+        src_port: u16,
+        dst_ip: &[u8; 4],
+        dst_port: u16,
+        proto: Protocol,
+    ) -> bool {
+        if !self.in_use || self.protocol != proto {
+            return false;
+        }
+
+        // IP addresses must match
+        if self.internal_ip != *src_ip || self.remote_ip != *dst_ip {
+            return false;
+        }
+
+        // Port matching depends on protocol
+        match proto {
+            Protocol::Icmp => {
+                // For ICMP: only match on the query identifier (stored in src_port)
+                self.internal_port == src_port
+            }
+            Protocol::Tcp | Protocol::Udp => {
+                // For TCP/UDP: match both ports
+                self.internal_port == src_port && self.remote_port == dst_port
+            }
+            // The client's call ID is not in its GRE, only the server's
+            Protocol::Gre => self.remote_port == dst_port,
+            // SPIs are checked by the ESP lookups
+            Protocol::Esp => true,
+            // Addresses only, the ports are always 0
+            Protocol::Other(_) => true,
+        }
+    }
+
+    /// Check if entry matches inbound packet
+    pub fn matches_inbound(
+        &self,
+        src_ip: &[u8; 4],
+        src_port: u16,
+        dst_port: u16,
+        proto: Protocol,
+    ) -> bool {
+        if !self.in_use || self.protocol != proto {
+            return false;
+        }
+
+        // Remote IP must match
+        if self.remote_ip != *src_ip {
+            return false;
+        }
+
+        // External port must match (this is what we're looking up by)
+        if self.external_port != dst_port {
+            return false;
+        }
+
+        // Port matching depends on protocol
+        match proto {
+            Protocol::Icmp => {
+                // For ICMP replies: the identifier was checked against
+                // external_port above, remote port doesn't matter
+                true
+            }
+            Protocol::Tcp | Protocol::Udp => {
+                // For TCP/UDP: also check remote port
+                self.remote_port == src_port
+            }
+            // The external call ID was checked against external_port above
+            Protocol::Gre => true,
+            Protocol::Esp | Protocol::Other(_) => true,
+        }
+    }
+
+    /// Hash key of this entry in the outbound index
+    pub fn outbound_hash(&self) -> u32 {
+        outbound_hash(
+            &self.internal_ip,
+            self.internal_port,
+            &self.remote_ip,
+            self.remote_port,
+            self.protocol,
+        )
+    }
+
+    /// Hash key of this entry in the inbound index
+    pub fn inbound_hash(&self) -> u32 {
+        inbound_hash(
+            &self.remote_ip,
+            self.remote_port,
+            self.external_port,
+            self.protocol,
+        )
+    }
+
+    /// Hash key of this entry in the internal endpoint index
+    pub fn endpoint_hash(&self) -> u32 {
+        endpoint_hash(&self.internal_ip, self.internal_port, self.protocol)
+    }
+
+    /// Hash key of this entry in the external port index
+    pub fn port_hash(&self) -> u32 {
+        port_hash(self.external_port, self.protocol)
+    }
+
+    /// Update last activity timestamp
+    pub fn touch(&mut self, now: u64) {
+        self.last_activity = now;
+    }
+
+    /// Advance the TCP state from the flags of a segment
+    pub fn track_tcp(&mut self, flags: u8, outbound: bool) {
+        if self.protocol != Protocol::Tcp {
+            return;
+        }
+
+        if flags & TCP_RST != 0 {
+            self.tcp_state = TcpState::Closed;
+            return;
+        }
+
+        let fin = flags & TCP_FIN != 0;
+        let syn_ack = flags & (TCP_SYN | TCP_ACK) == TCP_SYN | TCP_ACK;
+        let new_syn = flags & (TCP_SYN | TCP_ACK) == TCP_SYN;
+
+        self.tcp_state = match self.tcp_state {
+            TcpState::SynSent if syn_ack => TcpState::Established,
+            TcpState::Established if fin => TcpState::FinWait { by_lan: outbound },
+            TcpState::FinWait { by_lan } if fin && by_lan != outbound => TcpState::TimeWait,
+            // Port reuse after the previous connection went away
+            TcpState::TimeWait | TcpState::Closed if new_syn => TcpState::SynSent,
+            state => state,
+        };
+    }
+
+    /// Check if entry has been idle longer than `timeout_ms`
+    pub fn is_expired(&self, now: u64, timeout_ms: u32) -> bool {
+        if !self.in_use {
+            return true;
+        }
+        now.saturating_sub(self.last_activity) > timeout_ms as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::WRAP_32;
+
+    fn udp_entry(last_activity: u64) -> NatEntry {
+        let mut entry = NatEntry::new();
+        entry.protocol = Protocol::Udp;
+        entry.last_activity = last_activity;
+        entry.in_use = true;
+        entry
+    }
+
+    #[test]
+    fn expires_after_crossing_32bit_wrap() {
+        let entry = udp_entry(WRAP_32 - 1_000);
+
+        assert!(!entry.is_expired(WRAP_32 + 20_000, 30_000));
+        assert!(entry.is_expired(WRAP_32 + 30_000, 30_000));
+    }
+
+    #[test]
+    fn stays_fresh_when_touched_after_wrap() {
+        let mut entry = udp_entry(WRAP_32 - 1_000);
+        entry.touch(WRAP_32 + 25_000);
+
+        assert!(!entry.is_expired(WRAP_32 + 50_000, 30_000));
+        assert!(entry.is_expired(WRAP_32 + 60_000, 30_000));
+    }
+
+    #[test]
+    fn expires_long_after_wrap() {
+        // Idle for more than a full 32-bit period
+        let entry = udp_entry(WRAP_32 - 1_000);
+
+        assert!(entry.is_expired(2 * WRAP_32, 30_000));
+    }
+}
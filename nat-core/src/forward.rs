@@ -0,0 +1,252 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+use super::entry::Protocol;
+use super::table::NatError;
+use heapless::Vec;
+
+/// Port forwarding rules and leases held at once, unless configured
+/// otherwise
+pub const MAX_PORT_FORWARDS: usize = 16;
+
+/// Lifetime of a port forward requested by a LAN client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lease {
+    /// Uptime (ms) the mapping expires at
+    pub expires: u64,
+    /// PCP mapping nonce, zero for NAT-PMP
+    pub nonce: [u8; 12],
+}
+
+/// Port forwarding (DNAT) rule
+///
+/// External ports `external_start..=external_end` map one to one onto
+/// internal ports starting at `internal_port`. Rules without a lease are
+/// static and stay until removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortForward {
+    pub protocol: Protocol,
+    pub external_start: u16,
+    pub external_end: u16,
+    pub internal_ip: [u8; 4],
+    pub internal_port: u16,
+    pub lease: Option<Lease>,
+}
+
+impl PortForward {
+    fn covers(&self, external_port: u16, proto: Protocol) -> bool {
+        self.protocol == proto && (self.external_start..=self.external_end).contains(&external_port)
+    }
+
+    fn overlaps(&self, other: &PortForward) -> bool {
+        self.protocol == other.protocol
+            && self.external_start <= other.external_end
+            && other.external_start <= self.external_end
+    }
+
+    fn is_lease_of(&self, internal_ip: &[u8; 4], internal_port: u16, proto: Protocol) -> bool {
+        self.lease.is_some()
+            && self.protocol == proto
+            && self.internal_ip == *internal_ip
+            && self.internal_port == internal_port
+    }
+
+    fn internal_end(&self) -> u16 {
+        self.internal_port + (self.external_end - self.external_start)
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        matches!(self.lease, Some(lease) if now >= lease.expires)
+    }
+}
+
+/// Port forwarding rule table holding up to `F` rules and leases
+pub struct ForwardTable<const F: usize = MAX_PORT_FORWARDS> {
+    rules: Vec<PortForward, F>,
+}
+
+impl<const F: usize> Default for ForwardTable<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const F: usize> ForwardTable<F> {
+    pub const fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Add a rule, rejecting overlapping external ranges
+    pub fn add(&mut self, rule: PortForward) -> Result<(), NatError> {
+        if rule.protocol == Protocol::Icmp {
+            return Err(NatError::UnsupportedProtocol);
+        }
+
+        if rule.external_start > rule.external_end
+            || rule
+                .internal_port
+                .checked_add(rule.external_end - rule.external_start)
+                .is_none()
+            || self.rules.iter().any(|r| r.overlaps(&rule))
+        {
+            return Err(NatError::RuleConflict);
+        }
+
+        self.rules.push(rule).map_err(|_| NatError::TableFull)
+    }
+
+    /// Remove the rule starting at `external_start`
+    pub fn remove(&mut self, proto: Protocol, external_start: u16) -> Option<PortForward> {
+        let idx = self
+            .rules
+            .iter()
+            .position(|r| r.protocol == proto && r.external_start == external_start)?;
+        Some(self.rules.swap_remove(idx))
+    }
+
+    /// Leased rule of an internal endpoint, to renew in place
+    pub fn find_lease_mut(
+        &mut self,
+        internal_ip: &[u8; 4],
+        internal_port: u16,
+        proto: Protocol,
+    ) -> Option<&mut PortForward> {
+        self.rules
+            .iter_mut()
+            .find(|r| r.is_lease_of(internal_ip, internal_port, proto))
+    }
+
+    /// Lease of an internal endpoint to inspect, `None` when the endpoint
+    /// only has static rules or no rule at all
+    pub fn find_internal_lease(
+        &self,
+        internal_ip: &[u8; 4],
+        internal_port: u16,
+        proto: Protocol,
+    ) -> Option<&PortForward> {
+        self.rules
+            .iter()
+            .find(|r| r.is_lease_of(internal_ip, internal_port, proto))
+    }
+
+    /// Remove leased rules of an internal host, all its ports when
+    /// `internal_port` is `None` and all protocols when `proto` is `None`
+    ///
+    /// With a `nonce`, nothing is removed unless every such lease was
+    /// requested with it. Returns the number of rules removed.
+    pub fn remove_leases(
+        &mut self,
+        internal_ip: &[u8; 4],
+        internal_port: Option<u16>,
+        proto: Option<Protocol>,
+        nonce: Option<[u8; 12]>,
+    ) -> Result<usize, NatError> {
+        let selected = |r: &PortForward| {
+            r.internal_ip == *internal_ip
+                && proto.is_none_or(|proto| r.protocol == proto)
+                && internal_port.is_none_or(|port| r.internal_port == port)
+        };
+
+        let foreign = self
+            .rules
+            .iter()
+            .any(|r| selected(r) && matches!((r.lease, nonce), (Some(l), Some(n)) if l.nonce != n));
+        if foreign {
+            return Err(NatError::NotAuthorized);
+        }
+
+        let before = self.rules.len();
+        self.rules.retain(|r| r.lease.is_none() || !selected(r));
+        Ok(before - self.rules.len())
+    }
+
+    /// Leased rule mapping `external_port`
+    pub fn find_lease(&self, external_port: u16, proto: Protocol) -> Option<&PortForward> {
+        self.rules
+            .iter()
+            .find(|r| r.lease.is_some() && r.covers(external_port, proto))
+    }
+
+    /// Leased rules, in insertion order
+    pub fn leases(&self) -> impl Iterator<Item = &PortForward> {
+        self.rules.iter().filter(|r| r.lease.is_some())
+    }
+
+    /// Drop leases that ran out
+    pub fn expire_leases(&mut self, now: u64) -> usize {
+        let before = self.rules.len();
+        self.rules.retain(|r| !r.is_expired(now));
+        before - self.rules.len()
+    }
+
+    /// Check if an external port is reserved by a rule
+    pub fn covers(&self, external_port: u16, proto: Protocol) -> bool {
+        self.rules.iter().any(|r| r.covers(external_port, proto))
+    }
+
+    /// Internal destination of an inbound packet to `external_port`
+    pub fn find_inbound(&self, external_port: u16, proto: Protocol) -> Option<([u8; 4], u16)> {
+        self.rules
+            .iter()
+            .find(|r| r.covers(external_port, proto))
+            .map(|r| {
+                (
+                    r.internal_ip,
+                    r.internal_port + (external_port - r.external_start),
+                )
+            })
+    }
+
+    /// External port for traffic sent by a forwarded internal endpoint
+    pub fn find_outbound(
+        &self,
+        internal_ip: &[u8; 4],
+        internal_port: u16,
+        proto: Protocol,
+    ) -> Option<u16> {
+        self.rules
+            .iter()
+            .find(|r| {
+                r.protocol == proto
+                    && r.internal_ip == *internal_ip
+                    && (r.internal_port..=r.internal_end()).contains(&internal_port)
+            })
+            .map(|r| r.external_start + (internal_port - r.internal_port))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::WRAP_32;
+
+    fn lease_rule(port: u16, expires: u64) -> PortForward {
+        PortForward {
+            protocol: Protocol::Udp,
+            external_start: port,
+            external_end: port,
+            internal_ip: [192, 168, 4, 2],
+            internal_port: port,
+            lease: Some(Lease {
+                expires,
+                nonce: [0; 12],
+            }),
+        }
+    }
+
+    #[test]
+    fn leases_expire_across_32bit_wrap() {
+        let mut table: ForwardTable = ForwardTable::new();
+        table.add(lease_rule(6000, WRAP_32 + 10_000)).unwrap();
+        table.add(lease_rule(6001, WRAP_32 - 10_000)).unwrap();
+
+        assert_eq!(table.expire_leases(WRAP_32 - 20_000), 0);
+        assert_eq!(table.expire_leases(WRAP_32 + 1_000), 1);
+        assert!(table.covers(6000, Protocol::Udp));
+        assert!(!table.covers(6001, Protocol::Udp));
+        assert_eq!(table.expire_leases(WRAP_32 + 10_000), 1);
+        assert!(!table.covers(6000, Protocol::Udp));
+    }
+}
@@ -0,0 +1,186 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+use crate::packet::PacketContext;
+use crate::NetIf;
+use heapless::Vec;
+
+/// Fragmented datagrams tracked at once, upper bound of
+/// `FragmentPolicy::max_flows`
+pub const MAX_FRAGMENT_FLOWS: usize = 32;
+
+/// How later fragments are matched to the translation of their first one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FragmentPolicy {
+    /// Datagrams tracked at once, 0 drops every later fragment
+    pub max_flows: usize,
+    /// Time (ms) a datagram is tracked after its last fragment seen
+    pub timeout_ms: u32,
+}
+
+impl Default for FragmentPolicy {
+    fn default() -> Self {
+        Self {
+            max_flows: 16,
+            // Linux ipfrag_time
+            timeout_ms: 30_000,
+        }
+    }
+}
+
+/// Fields shared by every fragment of a datagram (RFC 791), as received
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FragmentKey {
+    pub src: [u8; 4],
+    pub dst: [u8; 4],
+    pub proto: u8,
+    pub id: u16,
+}
+
+impl FragmentKey {
+    pub fn of(ctx: &PacketContext) -> Self {
+        Self {
+            src: ctx.ip_hdr.src,
+            dst: ctx.ip_hdr.dst,
+            proto: ctx.ip_hdr.proto,
+            id: u16::from_be_bytes(ctx.ip_hdr.id),
+        }
+    }
+}
+
+/// Translation of a datagram whose first fragment went through the NAT
+#[derive(Clone, Copy)]
+struct FragmentFlow {
+    key: FragmentKey,
+    src: [u8; 4],
+    dst: [u8; 4],
+    iface: *mut NetIf,
+    /// Uptime (ms) the flow is dropped at
+    expires: u64,
+}
+
+/// Translations applied to the later fragments of recent datagrams
+///
+/// Fragments are not reassembled. A fragment arriving before the first one
+/// of its datagram finds no flow and is refused. Flows outlive the last
+/// fragment so reordered and duplicated fragments still pass, they only
+/// leave on timeout or when a newer datagram needs the room.
+pub struct FragmentTable {
+    flows: Vec<FragmentFlow, MAX_FRAGMENT_FLOWS>,
+    /// Later fragments that matched no flow
+    unmatched: u32,
+}
+
+impl Default for FragmentTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FragmentTable {
+    pub const fn new() -> Self {
+        Self {
+            flows: Vec::new(),
+            unmatched: 0,
+        }
+    }
+
+    /// Number of tracked datagrams
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    /// Later fragments refused since boot
+    pub fn unmatched(&self) -> u32 {
+        self.unmatched
+    }
+
+    /// Remember the translation of the first fragment received as `key`
+    pub fn track(
+        &mut self,
+        key: FragmentKey,
+        ctx: &PacketContext,
+        now: u64,
+        policy: &FragmentPolicy,
+    ) {
+        let limit = policy.max_flows.min(MAX_FRAGMENT_FLOWS);
+        if limit == 0 {
+            return;
+        }
+
+        let flow = FragmentFlow {
+            key,
+            src: ctx.ip_hdr.src,
+            dst: ctx.ip_hdr.dst,
+            iface: ctx.iface,
+            expires: now + policy.timeout_ms as u64,
+        };
+
+        if let Some(existing) = self.flows.iter_mut().find(|f| f.key == key) {
+            *existing = flow;
+            return;
+        }
+
+        // Make room by dropping the flows closest to expiry
+        while self.flows.len() >= limit {
+            let oldest = self
+                .flows
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, f)| f.expires)
+                .map(|(i, _)| i);
+            match oldest {
+                Some(i) => {
+                    self.flows.swap_remove(i);
+                }
+                None => break,
+            }
+        }
+        let _ = self.flows.push(flow);
+    }
+
+    /// Give a later fragment the addresses and interface of its first
+    /// fragment, `false` when its datagram is not tracked
+    pub fn translate(
+        &mut self,
+        ctx: &mut PacketContext,
+        now: u64,
+        policy: &FragmentPolicy,
+    ) -> bool {
+        let key = FragmentKey::of(ctx);
+
+        let flow = match self
+            .flows
+            .iter_mut()
+            .find(|f| f.key == key && f.expires > now)
+        {
+            Some(flow) => flow,
+            None => {
+                self.unmatched = self.unmatched.wrapping_add(1);
+                return false;
+            }
+        };
+
+        flow.expires = now + policy.timeout_ms as u64;
+        ctx.ip_hdr.src = flow.src;
+        ctx.ip_hdr.dst = flow.dst;
+        if !flow.iface.is_null() {
+            ctx.iface = flow.iface;
+        }
+        ctx.needs_update = true;
+
+        true
+    }
+
+    /// Drop timed out flows, returning how many
+    pub fn expire(&mut self, now: u64) -> usize {
+        let before = self.flows.len();
+        self.flows.retain(|f| f.expires > now);
+        before - self.flows.len()
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+use super::entry::Protocol;
+
+/// Marker for an unused bucket
+const EMPTY: u16 = u16::MAX;
+
+/// FNV-1a parameters (32 bit)
+const FNV_OFFSET: u32 = 0x811C_9DC5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+/// Fixed-capacity open-addressing hash index.
+///
+/// Buckets only store slot numbers of the NAT table; the caller supplies
+/// the hash and a predicate that compares the key against the slot. `N`
+/// is the number of slots that may be indexed; two buckets per slot keep
+/// the index at most half full so probe chains stay short.
+pub struct HashIndex<const N: usize> {
+    buckets: [[u16; 2]; N],
+}
+
+impl<const N: usize> Default for HashIndex<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> HashIndex<N> {
+    const LEN: usize = 2 * N;
+
+    pub const fn new() -> Self {
+        Self {
+            buckets: [[EMPTY; 2]; N],
+        }
+    }
+
+    fn bucket(&self, pos: usize) -> u16 {
+        self.buckets[pos / 2][pos % 2]
+    }
+
+    fn set_bucket(&mut self, pos: usize, slot: u16) {
+        self.buckets[pos / 2][pos % 2] = slot;
+    }
+
+    fn home(hash: u32) -> usize {
+        hash as usize % Self::LEN
+    }
+
+    fn next(pos: usize) -> usize {
+        (pos + 1) % Self::LEN
+    }
+
+    /// Find the first slot in the probe chain of `hash` accepted by `is_match`
+    pub fn find(&self, hash: u32, mut is_match: impl FnMut(usize) -> bool) -> Option<usize> {
+        let mut pos = Self::home(hash);
+        for _ in 0..Self::LEN {
+            let slot = self.bucket(pos);
+            if slot == EMPTY {
+                return None;
+            }
+            if is_match(slot as usize) {
+                return Some(slot as usize);
+            }
+            pos = Self::next(pos);
+        }
+        None
+    }
+
+    /// Insert slot under `hash`. Returns false if the index is full.
+    pub fn insert(&mut self, hash: u32, slot: usize) -> bool {
+        let mut pos = Self::home(hash);
+        for _ in 0..Self::LEN {
+            if self.bucket(pos) == EMPTY {
+                self.set_bucket(pos, slot as u16);
+                return true;
+            }
+            pos = Self::next(pos);
+        }
+        false
+    }
+
+    /// Remove slot stored under `hash`.
+    ///
+    /// Uses backward-shift deletion so probe chains stay intact without
+    /// tombstones; `hash_of` must return the hash every other indexed slot
+    /// was inserted with.
+    pub fn remove(&mut self, hash: u32, slot: usize, hash_of: impl Fn(usize) -> u32) -> bool {
+        let mut hole = Self::home(hash);
+        let mut probes = 0;
+        loop {
+            let current = self.bucket(hole);
+            if current == EMPTY || probes == Self::LEN {
+                return false;
+            }
+            if current as usize == slot {
+                break;
+            }
+            hole = Self::next(hole);
+            probes += 1;
+        }
+
+        // Cyclic distance from `from` forward to `to`
+        let distance = |from: usize, to: usize| (to + Self::LEN - from) % Self::LEN;
+
+        let mut next = Self::next(hole);
+        loop {
+            let moved = self.bucket(next);
+            if moved == EMPTY {
+                break;
+            }
+            let home = Self::home(hash_of(moved as usize));
+            // Shift back unless the bucket's home lies cyclically in (hole, next]
+            if distance(home, next) >= distance(hole, next) {
+                self.set_bucket(hole, moved);
+                hole = next;
+            }
+            next = Self::next(next);
+        }
+        self.set_bucket(hole, EMPTY);
+        true
+    }
+}
+
+fn fnv1a(mut hash: u32, bytes: &[u8]) -> u32 {
+    for b in bytes {
+        hash ^= *b as u32;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// Hash of the outbound 5-tuple (internal ip:port -> remote ip:port, proto)
+pub fn outbound_hash(
+    internal_ip: &[u8; 4],
+    internal_port: u16,
+    remote_ip: &[u8; 4],
+    remote_port: u16,
+    proto: Protocol,
+) -> u32 {
+    // ICMP matches on the identifier only, remote "port" is not part of the key
+    let remote_port = if proto == Protocol::Icmp {
+        0
+    } else {
+        remote_port
+    };
+    // GRE sent by the client only carries the server's call ID
+    let internal_port = if proto == Protocol::Gre {
+        0
+    } else {
+        internal_port
+    };
+
+    let mut hash = fnv1a(FNV_OFFSET, internal_ip);
+    hash = fnv1a(hash, &internal_port.to_be_bytes());
+    hash = fnv1a(hash, remote_ip);
+    hash = fnv1a(hash, &remote_port.to_be_bytes());
+    fnv1a(hash, &[proto.number()])
+}
+
+/// Hash of the inbound tuple (remote ip:port -> external port, proto)
+pub fn inbound_hash(
+    remote_ip: &[u8; 4],
+    remote_port: u16,
+    external_port: u16,
+    proto: Protocol,
+) -> u32 {
+    let remote_port = if matches!(proto, Protocol::Icmp | Protocol::Gre) {
+        0
+    } else {
+        remote_port
+    };
+
+    let mut hash = fnv1a(FNV_OFFSET, remote_ip);
+    hash = fnv1a(hash, &remote_port.to_be_bytes());
+    hash = fnv1a(hash, &external_port.to_be_bytes());
+    fnv1a(hash, &[proto.number()])
+}
+
+/// Hash of the external (port, proto) pair
+pub fn port_hash(external_port: u16, proto: Protocol) -> u32 {
+    let hash = fnv1a(FNV_OFFSET, &external_port.to_be_bytes());
+    fnv1a(hash, &[proto.number()])
+}
+
+/// Hash of the internal endpoint (ip, port, proto)
+pub fn endpoint_hash(internal_ip: &[u8; 4], internal_port: u16, proto: Protocol) -> u32 {
+    let mut hash = fnv1a(FNV_OFFSET, internal_ip);
+    hash = fnv1a(hash, &internal_port.to_be_bytes());
+    fnv1a(hash, &[proto.number()])
+}
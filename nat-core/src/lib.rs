@@ -0,0 +1,37 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+//! IPv4 NAT core: session table, port forwards and header rewriting.
+//!
+//! Nothing here depends on Zephyr. The embedding firmware supplies the
+//! table capacity, a `Clock`, the configuration and the raw header bytes,
+//! so the same code runs in `cargo test` on a development host.
+
+#![no_std]
+
+pub mod checksum;
+pub mod clock;
+pub mod entry;
+pub mod forward;
+pub mod fragment;
+pub mod index;
+pub mod packet;
+pub mod pptp;
+pub mod quota;
+pub mod table;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod timeout;
+
+pub use clock::Clock;
+pub use packet::PacketContext;
+pub use table::{NatConfig, NatError, NatStats, NatTable, SessionEnd, SessionEndReason};
+
+/// Network interface of the embedding stack
+///
+/// Only pointers to it are stored and compared, it is never dereferenced.
+#[repr(C)]
+pub struct NetIf {
+    _private: [u8; 0],
+}
@@ -0,0 +1,626 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+use crate::checksum::{ip_checksum, update_checksum};
+use crate::entry::Protocol;
+use crate::pptp::{gre_call_id, PptpMessage, GRE_CALL_ID, PPTP_PORT};
+use crate::NetIf;
+
+/// IPv4 header structure (matching Zephyr's net_ipv4_hdr)
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct Ipv4Hdr {
+    pub vhl: u8,         // Version + IHL
+    pub tos: u8,         // Type of service
+    pub len: [u8; 2],    // Total length (big-endian)
+    pub id: [u8; 2],     // Identification
+    pub offset: [u8; 2], // Flags + fragment offset
+    pub ttl: u8,         // Time to live
+    pub proto: u8,       // Protocol (TCP=6, UDP=17, ICMP=1)
+    pub chksum: u16,     // Header checksum
+    pub src: [u8; 4],    // Source IP
+    pub dst: [u8; 4],    // Destination IP
+}
+
+/// TCP header flags
+pub const TCP_FIN: u8 = 0x01;
+pub const TCP_SYN: u8 = 0x02;
+pub const TCP_RST: u8 = 0x04;
+pub const TCP_ACK: u8 = 0x10;
+
+/// ICMP message types
+pub const ICMP_ECHO_REPLY: u8 = 0;
+pub const ICMP_DEST_UNREACH: u8 = 3;
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+pub const ICMP_PARAM_PROBLEM: u8 = 12;
+pub const ICMP_TIMESTAMP_REQUEST: u8 = 13;
+pub const ICMP_TIMESTAMP_REPLY: u8 = 14;
+pub const ICMP_INFO_REQUEST: u8 = 15;
+pub const ICMP_INFO_REPLY: u8 = 16;
+pub const ICMP_ADDR_MASK_REQUEST: u8 = 17;
+pub const ICMP_ADDR_MASK_REPLY: u8 = 18;
+
+/// Protocols keyed on other fields than ports
+pub const IPPROTO_GRE: u8 = 47;
+pub const IPPROTO_ESP: u8 = 50;
+
+/// Address-only protocols whose checksum, at offset 6, covers the IPv4
+/// pseudo header
+pub const IPPROTO_DCCP: u8 = 33;
+pub const IPPROTO_UDPLITE: u8 = 136;
+
+/// IPv4 more fragments flag and fragment offset mask
+const IP_MF: u16 = 0x2000;
+const IP_OFFSET_MASK: u16 = 0x1FFF;
+
+/// Bytes from the start of a datagram that `parse` and `apply` touch:
+/// the IPv4 header with options, the ICMP header and the IPv4 and TCP
+/// headers quoted by an ICMP error. This also covers the call IDs of a
+/// PPTP control message behind a TCP header.
+pub const MAX_HEADER_LEN: usize = 60 + 8 + 60 + 20;
+
+#[derive(Clone, Copy)]
+pub struct PacketContext {
+    pub ip_hdr: Ipv4Hdr,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub tcp_flags: u8,
+    pub icmp_type: u8,
+    /// Packet embedded in an ICMP error message
+    pub inner: Option<IcmpInner>,
+    /// PPTP enhanced GRE, `dst_port` holds the call ID
+    pub pptp_gre: bool,
+    /// ESP security parameter index
+    pub spi: u32,
+    /// PPTP control message of a TCP segment to or from port 1723
+    pub pptp: Option<PptpMessage>,
+    pub needs_update: bool,
+    pub iface: *mut NetIf,
+    pub orig_iface: *mut NetIf,
+}
+
+/// ICMP query messages carrying an identifier (RFC 5508 section 3.1)
+pub fn is_icmp_query(icmp_type: u8) -> bool {
+    matches!(
+        icmp_type,
+        ICMP_ECHO_REPLY
+            | ICMP_ECHO_REQUEST
+            | ICMP_TIMESTAMP_REQUEST
+            | ICMP_TIMESTAMP_REPLY
+            | ICMP_INFO_REQUEST
+            | ICMP_INFO_REPLY
+            | ICMP_ADDR_MASK_REQUEST
+            | ICMP_ADDR_MASK_REPLY
+    )
+}
+
+/// ICMP error messages quoting the offending packet (RFC 5508 section 4)
+pub fn is_icmp_error(icmp_type: u8) -> bool {
+    matches!(
+        icmp_type,
+        ICMP_DEST_UNREACH | ICMP_TIME_EXCEEDED | ICMP_PARAM_PROBLEM
+    )
+}
+
+/// IPv4 + L4 header quoted inside an ICMP error message
+///
+/// Ports follow the same convention as `PacketContext`: for an embedded
+/// ICMP query both hold the identifier.
+#[derive(Clone, Copy)]
+pub struct IcmpInner {
+    pub src: [u8; 4],
+    pub dst: [u8; 4],
+    pub proto: u8,
+    pub src_port: u16,
+    pub dst_port: u16,
+    /// Embedded IP header length
+    pub ihl: usize,
+}
+
+impl IcmpInner {
+    /// Parse the embedded packet from a whole ICMP error message
+    fn parse(icmp: &[u8]) -> Option<Self> {
+        let body = icmp.get(8..)?;
+        let vhl = *body.first()?;
+        if vhl >> 4 != 4 {
+            return None;
+        }
+
+        let ihl = ((vhl & 0x0F) as usize) * 4;
+        if ihl < 20 {
+            return None;
+        }
+
+        // RFC 792: at least 64 bits of the original datagram are quoted
+        let ip = body.get(..ihl)?;
+        let l4 = body.get(ihl..ihl + 8)?;
+
+        let proto = ip[9];
+        let (src_port, dst_port) = match proto {
+            6 | 17 => (
+                u16::from_be_bytes([l4[0], l4[1]]),
+                u16::from_be_bytes([l4[2], l4[3]]),
+            ),
+            1 => {
+                if !is_icmp_query(l4[0]) {
+                    return None;
+                }
+                let id = u16::from_be_bytes([l4[4], l4[5]]);
+                (id, id)
+            }
+            // Address-only protocols have no ports to translate
+            _ => (0, 0),
+        };
+
+        Some(Self {
+            src: [ip[12], ip[13], ip[14], ip[15]],
+            dst: [ip[16], ip[17], ip[18], ip[19]],
+            proto,
+            src_port,
+            dst_port,
+            ihl,
+        })
+    }
+}
+
+/// Store a big-endian word and return the one it replaced
+fn swap_word(buf: &mut [u8], off: usize, new: u16) -> u16 {
+    let old = u16::from_be_bytes([buf[off], buf[off + 1]]);
+    buf[off..off + 2].copy_from_slice(&new.to_be_bytes());
+    old
+}
+
+/// Write the call IDs of a PPTP control message into a TCP segment
+fn update_pptp(tcp: &mut [u8], msg: &PptpMessage) {
+    if tcp.len() < 20 {
+        return;
+    }
+
+    let csum = u16::from_be_bytes([tcp[16], tcp[17]]);
+    let csum = msg.apply(tcp, csum);
+    tcp[16..18].copy_from_slice(&csum.to_be_bytes());
+}
+
+/// Bytes of L4 data present in `data`
+fn l4_avail(data: &[u8], ihl: usize) -> usize {
+    let total_len = u16::from_be_bytes([data[2], data[3]]) as usize;
+    total_len.min(data.len()).saturating_sub(ihl)
+}
+
+impl PacketContext {
+    /// Part of a fragmented datagram
+    pub fn is_fragment(&self) -> bool {
+        u16::from_be_bytes(self.ip_hdr.offset) & (IP_MF | IP_OFFSET_MASK) != 0
+    }
+
+    /// Fragment other than the first, without L4 header
+    pub fn is_later_fragment(&self) -> bool {
+        u16::from_be_bytes(self.ip_hdr.offset) & IP_OFFSET_MASK != 0
+    }
+
+    /// Protocol the packet is mapped with
+    pub fn protocol(&self) -> Protocol {
+        match Protocol::from_ip(self.ip_hdr.proto) {
+            Protocol::Other(IPPROTO_GRE) if self.pptp_gre => Protocol::Gre,
+            proto => proto,
+        }
+    }
+
+    /// Parse the IPv4 and L4 headers at the start of `data`
+    ///
+    /// `data` holds the bytes available contiguously, which may be less
+    /// than the datagram; missing L4 fields are left at 0.
+    pub fn parse(data: &[u8], iface: *mut NetIf) -> Option<Self> {
+        let min_hdr = data.get(..20)?;
+
+        let vhl = min_hdr[0];
+        if vhl >> 4 != 4 {
+            return None;
+        }
+
+        let ihl = ((vhl & 0x0F) as usize) * 4;
+        if !(20..=60).contains(&ihl) {
+            return None;
+        }
+
+        let full_hdr = data.get(..ihl)?;
+
+        let ip_hdr = Ipv4Hdr {
+            vhl,
+            tos: full_hdr[1],
+            len: [full_hdr[2], full_hdr[3]],
+            id: [full_hdr[4], full_hdr[5]],
+            offset: [full_hdr[6], full_hdr[7]],
+            ttl: full_hdr[8],
+            proto: full_hdr[9],
+            chksum: u16::from_be_bytes([full_hdr[10], full_hdr[11]]),
+            src: [full_hdr[12], full_hdr[13], full_hdr[14], full_hdr[15]],
+            dst: [full_hdr[16], full_hdr[17], full_hdr[18], full_hdr[19]],
+        };
+
+        // Later fragments carry payload only, there is no L4 header to read
+        let later_fragment = u16::from_be_bytes(ip_hdr.offset) & IP_OFFSET_MASK != 0;
+        let l4 = if later_fragment {
+            &[][..]
+        } else {
+            &data[ihl..ihl + l4_avail(data, ihl)]
+        };
+        let mut tcp_flags = 0;
+        let mut icmp_type = 0;
+        let mut inner = None;
+        let mut pptp_gre = false;
+        let mut spi = 0;
+
+        let (src_port, dst_port) = match ip_hdr.proto {
+            6 | 17 if l4.len() >= 4 => (
+                u16::from_be_bytes([l4[0], l4[1]]),
+                u16::from_be_bytes([l4[2], l4[3]]),
+            ),
+            1 => {
+                icmp_type = l4.first().copied().unwrap_or(0);
+                if l4.len() >= 8 && is_icmp_query(icmp_type) {
+                    // The query identifier acts as both ports: outbound NAT
+                    // rewrites it as the source, inbound as the destination
+                    let id = u16::from_be_bytes([l4[4], l4[5]]);
+                    (id, id)
+                } else if is_icmp_error(icmp_type) {
+                    inner = IcmpInner::parse(l4);
+                    (0, 0)
+                } else {
+                    (0, 0)
+                }
+            }
+            IPPROTO_GRE => match gre_call_id(l4) {
+                Some(call_id) => {
+                    pptp_gre = true;
+                    (0, call_id)
+                }
+                None => (0, 0),
+            },
+            IPPROTO_ESP => {
+                if let Some(hdr) = l4.get(..4) {
+                    spi = u32::from_be_bytes([hdr[0], hdr[1], hdr[2], hdr[3]]);
+                }
+                (0, 0)
+            }
+            _ => (0, 0),
+        };
+
+        if ip_hdr.proto == 6 && l4.len() >= 14 {
+            tcp_flags = l4[13];
+        }
+
+        let pptp = if ip_hdr.proto == 6 && (src_port == PPTP_PORT || dst_port == PPTP_PORT) {
+            PptpMessage::parse(l4)
+        } else {
+            None
+        };
+
+        Some(Self {
+            ip_hdr,
+            src_port,
+            dst_port,
+            tcp_flags,
+            icmp_type,
+            inner,
+            pptp_gre,
+            spi,
+            pptp,
+            needs_update: false,
+            iface,
+            orig_iface: iface,
+        })
+    }
+
+    /// Write the translated addresses, ports and checksums into `data`,
+    /// the same bytes the context was parsed from
+    pub fn apply(&self, data: &mut [u8]) {
+        if !self.needs_update || data.len() < 20 {
+            return;
+        }
+
+        let ihl = ((self.ip_hdr.vhl & 0x0F) as usize) * 4;
+        if ihl < 20 || data.len() < ihl {
+            return;
+        }
+        let l4_len = l4_avail(data, ihl);
+
+        let old_src_ip = [data[12], data[13], data[14], data[15]];
+        let old_dst_ip = [data[16], data[17], data[18], data[19]];
+
+        data[12..16].copy_from_slice(&self.ip_hdr.src);
+        data[16..20].copy_from_slice(&self.ip_hdr.dst);
+
+        // === IP Checksum  ===
+        let ip_changed = old_src_ip != self.ip_hdr.src || old_dst_ip != self.ip_hdr.dst;
+
+        if ip_changed {
+            let ip_hdr_full = &mut data[..ihl];
+            ip_hdr_full[10] = 0;
+            ip_hdr_full[11] = 0;
+            let csum = ip_checksum(ip_hdr_full);
+            ip_hdr_full[10] = (csum >> 8) as u8;
+            ip_hdr_full[11] = csum as u8;
+        }
+
+        // Later fragments have no L4 header, their payload is left alone
+        if self.is_later_fragment() {
+            return;
+        }
+
+        // === Transport Layer (TCP/UDP/ICMP) ===
+        let l4 = &mut data[ihl..ihl + l4_len];
+
+        match self.ip_hdr.proto {
+            6 => {
+                // TCP
+                if let Some(msg) = self.pptp {
+                    update_pptp(l4, &msg);
+                }
+                self.update_tcp_checksum(l4, old_src_ip, old_dst_ip, ip_changed);
+            }
+            17 => {
+                // UDP
+                self.update_udp_checksum(l4, old_src_ip, old_dst_ip, ip_changed);
+            }
+            1 if is_icmp_query(self.icmp_type) => {
+                // ICMP
+                self.update_icmp_query(l4);
+            }
+            1 if self.inner.is_some() => {
+                // ICMP error with embedded packet
+                self.update_icmp_error(l4);
+            }
+            IPPROTO_GRE if self.pptp_gre => {
+                // No checksum in enhanced GRE
+                if let Some(call_id) = l4.get_mut(GRE_CALL_ID..GRE_CALL_ID + 2) {
+                    call_id.copy_from_slice(&self.dst_port.to_be_bytes());
+                }
+            }
+            IPPROTO_DCCP | IPPROTO_UDPLITE if ip_changed => {
+                self.update_pseudo_checksum(l4, old_src_ip, old_dst_ip);
+            }
+            _ => {}
+        }
+    }
+
+    #[inline(always)]
+    fn update_tcp_checksum(
+        &self,
+        l4: &mut [u8],
+        old_src_ip: [u8; 4],
+        old_dst_ip: [u8; 4],
+        ip_changed: bool,
+    ) {
+        let tcp_hdr = match l4.get_mut(..20) {
+            Some(hdr) => hdr,
+            None => return,
+        };
+
+        let old_src_port = u16::from_be_bytes([tcp_hdr[0], tcp_hdr[1]]);
+        let old_dst_port = u16::from_be_bytes([tcp_hdr[2], tcp_hdr[3]]);
+        let mut csum = u16::from_be_bytes([tcp_hdr[16], tcp_hdr[17]]);
+
+        // Port güncellemeleri
+        tcp_hdr[0..2].copy_from_slice(&self.src_port.to_be_bytes());
+        tcp_hdr[2..4].copy_from_slice(&self.dst_port.to_be_bytes());
+
+        // Port değişiklikleri için checksum güncelle
+        if old_src_port != self.src_port {
+            csum = update_checksum(csum, old_src_port, self.src_port);
+        }
+        if old_dst_port != self.dst_port {
+            csum = update_checksum(csum, old_dst_port, self.dst_port);
+        }
+
+        // IP değişiklikleri için checksum güncelle
+        if ip_changed {
+            csum = self.update_checksum_for_ip(csum, old_src_ip, old_dst_ip);
+        }
+
+        tcp_hdr[16..18].copy_from_slice(&csum.to_be_bytes());
+    }
+
+    #[inline(always)]
+    fn update_udp_checksum(
+        &self,
+        l4: &mut [u8],
+        old_src_ip: [u8; 4],
+        old_dst_ip: [u8; 4],
+        ip_changed: bool,
+    ) {
+        let udp_hdr = match l4.get_mut(..8) {
+            Some(hdr) => hdr,
+            None => return,
+        };
+
+        let old_src_port = u16::from_be_bytes([udp_hdr[0], udp_hdr[1]]);
+        let old_dst_port = u16::from_be_bytes([udp_hdr[2], udp_hdr[3]]);
+        let mut csum = u16::from_be_bytes([udp_hdr[6], udp_hdr[7]]);
+
+        // Port güncellemeleri
+        udp_hdr[0..2].copy_from_slice(&self.src_port.to_be_bytes());
+        udp_hdr[2..4].copy_from_slice(&self.dst_port.to_be_bytes());
+
+        // UDP checksum 0 ise güncelleme yapma (opsiyonel checksum)
+        if csum != 0 {
+            // Port değişiklikleri için checksum güncelle
+            if old_src_port != self.src_port {
+                csum = update_checksum(csum, old_src_port, self.src_port);
+            }
+            if old_dst_port != self.dst_port {
+                csum = update_checksum(csum, old_dst_port, self.dst_port);
+            }
+
+            // IP değişiklikleri için checksum güncelle
+            if ip_changed {
+                csum = self.update_checksum_for_ip(csum, old_src_ip, old_dst_ip);
+            }
+
+            udp_hdr[6..8].copy_from_slice(&csum.to_be_bytes());
+        }
+    }
+
+    /// Checksum of an address-only protocol after an address change
+    #[inline(always)]
+    fn update_pseudo_checksum(&self, l4: &mut [u8], old_src_ip: [u8; 4], old_dst_ip: [u8; 4]) {
+        let hdr = match l4.get_mut(..8) {
+            Some(hdr) => hdr,
+            None => return,
+        };
+
+        let csum = u16::from_be_bytes([hdr[6], hdr[7]]);
+        let csum = self.update_checksum_for_ip(csum, old_src_ip, old_dst_ip);
+        hdr[6..8].copy_from_slice(&csum.to_be_bytes());
+    }
+
+    #[inline(always)]
+    fn update_icmp_query(&self, l4: &mut [u8]) {
+        let icmp_hdr = match l4.get_mut(..8) {
+            Some(hdr) => hdr,
+            None => return,
+        };
+
+        let old_id = u16::from_be_bytes([icmp_hdr[4], icmp_hdr[5]]);
+        // Only one side of the identifier is translated per direction
+        let new_id = if self.src_port != old_id {
+            self.src_port
+        } else {
+            self.dst_port
+        };
+
+        if old_id == new_id {
+            return;
+        }
+
+        // ICMP checksum has no pseudo header, only the identifier changes
+        let csum = u16::from_be_bytes([icmp_hdr[2], icmp_hdr[3]]);
+        let csum = update_checksum(csum, old_id, new_id);
+
+        icmp_hdr[4..6].copy_from_slice(&new_id.to_be_bytes());
+        icmp_hdr[2..4].copy_from_slice(&csum.to_be_bytes());
+    }
+
+    /// Rewrite the embedded packet of an ICMP error (RFC 5508 REQ-4)
+    ///
+    /// Inner addresses and ports are replaced, the inner IP and L4
+    /// checksums are fixed when present, and every changed word is folded
+    /// into the outer ICMP checksum.
+    fn update_icmp_error(&self, icmp: &mut [u8]) {
+        let inner = match self.inner {
+            Some(inner) => inner,
+            None => return,
+        };
+
+        if icmp.len() < 8 + inner.ihl + 8 {
+            return;
+        }
+
+        let mut csum = u16::from_be_bytes([icmp[2], icmp[3]]);
+
+        let (_, body) = icmp.split_at_mut(8);
+        let (ip, l4) = body.split_at_mut(inner.ihl);
+
+        // Inner L4 checksum, if quoted and in use
+        let l4_csum_off = match inner.proto {
+            6 if l4.len() >= 18 => Some(16),
+            17 if l4[6] != 0 || l4[7] != 0 => Some(6),
+            IPPROTO_DCCP | IPPROTO_UDPLITE => Some(6),
+            1 => Some(2),
+            _ => None,
+        };
+        let pseudo_hdr = inner.proto != 1;
+        let mut l4_csum = l4_csum_off.map(|off| u16::from_be_bytes([l4[off], l4[off + 1]]));
+        let mut ip_csum = u16::from_be_bytes([ip[10], ip[11]]);
+
+        // Inner addresses
+        for (off, addr) in [(12, inner.src), (16, inner.dst)] {
+            for k in 0..2 {
+                let new = u16::from_be_bytes([addr[2 * k], addr[2 * k + 1]]);
+                let old = swap_word(ip, off + 2 * k, new);
+                if old != new {
+                    ip_csum = update_checksum(ip_csum, old, new);
+                    csum = update_checksum(csum, old, new);
+                    if pseudo_hdr {
+                        l4_csum = l4_csum.map(|c| update_checksum(c, old, new));
+                    }
+                }
+            }
+        }
+
+        let old = swap_word(ip, 10, ip_csum);
+        csum = update_checksum(csum, old, ip_csum);
+
+        // Inner ports / query identifier
+        if inner.proto == 1 {
+            let old_id = u16::from_be_bytes([l4[4], l4[5]]);
+            let new_id = if inner.src_port != old_id {
+                inner.src_port
+            } else {
+                inner.dst_port
+            };
+            if old_id != new_id {
+                swap_word(l4, 4, new_id);
+                csum = update_checksum(csum, old_id, new_id);
+                l4_csum = l4_csum.map(|c| update_checksum(c, old_id, new_id));
+            }
+        } else if matches!(inner.proto, 6 | 17) {
+            for (off, new) in [(0, inner.src_port), (2, inner.dst_port)] {
+                let old = swap_word(l4, off, new);
+                if old != new {
+                    csum = update_checksum(csum, old, new);
+                    l4_csum = l4_csum.map(|c| update_checksum(c, old, new));
+                }
+            }
+        }
+
+        if let (Some(off), Some(new)) = (l4_csum_off, l4_csum) {
+            let old = swap_word(l4, off, new);
+            csum = update_checksum(csum, old, new);
+        }
+
+        icmp[2..4].copy_from_slice(&csum.to_be_bytes());
+    }
+
+    #[inline(always)]
+    fn update_checksum_for_ip(
+        &self,
+        mut csum: u16,
+        old_src_ip: [u8; 4],
+        old_dst_ip: [u8; 4],
+    ) -> u16 {
+        // Kaynak IP değiştiyse
+        if old_src_ip != self.ip_hdr.src {
+            let old_src_hi = u16::from_be_bytes([old_src_ip[0], old_src_ip[1]]);
+            let old_src_lo = u16::from_be_bytes([old_src_ip[2], old_src_ip[3]]);
+            let new_src_hi = u16::from_be_bytes([self.ip_hdr.src[0], self.ip_hdr.src[1]]);
+            let new_src_lo = u16::from_be_bytes([self.ip_hdr.src[2], self.ip_hdr.src[3]]);
+
+            if old_src_hi != new_src_hi {
+                csum = update_checksum(csum, old_src_hi, new_src_hi);
+            }
+            if old_src_lo != new_src_lo {
+                csum = update_checksum(csum, old_src_lo, new_src_lo);
+            }
+        }
+
+        // Hedef IP değiştiyse
+        if old_dst_ip != self.ip_hdr.dst {
+            let old_dst_hi = u16::from_be_bytes([old_dst_ip[0], old_dst_ip[1]]);
+            let old_dst_lo = u16::from_be_bytes([old_dst_ip[2], old_dst_ip[3]]);
+            let new_dst_hi = u16::from_be_bytes([self.ip_hdr.dst[0], self.ip_hdr.dst[1]]);
+            let new_dst_lo = u16::from_be_bytes([self.ip_hdr.dst[2], self.ip_hdr.dst[3]]);
+
+            if old_dst_hi != new_dst_hi {
+                csum = update_checksum(csum, old_dst_hi, new_dst_hi);
+            }
+            if old_dst_lo != new_dst_lo {
+                csum = update_checksum(csum, old_dst_lo, new_dst_lo);
+            }
+        }
+
+        csum
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+//! PPTP control connection (RFC 2637) as seen by the NAT.
+//!
+//! The data of a PPTP call travels in enhanced GRE packets that carry the
+//! receiver's call ID instead of ports. The call IDs chosen by LAN clients
+//! are swapped for unique external ones in the control messages, so GRE
+//! coming back can be told apart per client.
+
+use crate::checksum::update_checksum;
+
+pub const PPTP_PORT: u16 = 1723;
+
+/// Control message types carrying a call ID
+pub const OUTGOING_CALL_REQUEST: u16 = 7;
+pub const OUTGOING_CALL_REPLY: u16 = 8;
+pub const CALL_CLEAR_REQUEST: u16 = 12;
+pub const CALL_DISCONNECT_NOTIFY: u16 = 13;
+pub const WAN_ERROR_NOTIFY: u16 = 14;
+pub const SET_LINK_INFO: u16 = 15;
+
+const CONTROL_MESSAGE: u16 = 1;
+const MAGIC_COOKIE: u32 = 0x1A2B_3C4D;
+
+/// Bytes of a control message up to the Peer's Call ID field
+pub const PPTP_HEADER_LEN: usize = 16;
+
+/// Enhanced GRE (RFC 2637 section 4.1): key present, sequence number
+/// optional, no checksum or routing
+const GRE_FLAGS_MASK: u8 = 0xEF;
+const GRE_FLAGS: u8 = 0x20;
+/// Acknowledgment number optional, version 1
+const GRE_VERSION_MASK: u8 = 0x7F;
+const GRE_VERSION_PPTP: u8 = 1;
+const GRE_PROTO_PPP: u16 = 0x880B;
+
+/// Offset of the call ID in an enhanced GRE header
+pub const GRE_CALL_ID: usize = 6;
+
+/// Call ID of an enhanced GRE packet, `None` for other GRE packets
+pub fn gre_call_id(gre: &[u8]) -> Option<u16> {
+    let hdr = gre.get(..8)?;
+    if hdr[0] & GRE_FLAGS_MASK != GRE_FLAGS
+        || hdr[1] & GRE_VERSION_MASK != GRE_VERSION_PPTP
+        || u16::from_be_bytes([hdr[2], hdr[3]]) != GRE_PROTO_PPP
+    {
+        return None;
+    }
+
+    Some(u16::from_be_bytes([hdr[GRE_CALL_ID], hdr[GRE_CALL_ID + 1]]))
+}
+
+/// Control message at the start of a TCP segment to or from port 1723
+///
+/// Only the first message of a segment is looked at, clients send the
+/// call setup messages one per segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PptpMessage {
+    /// Control message type
+    pub kind: u16,
+    /// Call ID field, the sender's own or its peer's depending on `kind`
+    pub call_id: u16,
+    /// Peer's Call ID of an Outgoing-Call-Reply
+    pub peer_call_id: u16,
+    /// Offset of the message in the TCP segment
+    offset: usize,
+}
+
+impl PptpMessage {
+    /// Parse the message following the TCP header of `tcp`
+    pub fn parse(tcp: &[u8]) -> Option<Self> {
+        let offset = ((*tcp.get(12)? >> 4) as usize) * 4;
+        let msg = tcp.get(offset..offset + PPTP_HEADER_LEN)?;
+
+        let msg_type = u16::from_be_bytes([msg[2], msg[3]]);
+        let cookie = u32::from_be_bytes([msg[4], msg[5], msg[6], msg[7]]);
+        if msg_type != CONTROL_MESSAGE || cookie != MAGIC_COOKIE {
+            return None;
+        }
+
+        Some(Self {
+            kind: u16::from_be_bytes([msg[8], msg[9]]),
+            call_id: u16::from_be_bytes([msg[12], msg[13]]),
+            peer_call_id: u16::from_be_bytes([msg[14], msg[15]]),
+            offset,
+        })
+    }
+
+    /// Write the call IDs into `tcp`, returning the TCP checksum adjusted
+    /// for the change
+    pub fn apply(&self, tcp: &mut [u8], mut csum: u16) -> u16 {
+        for (field, new) in [(12, self.call_id), (14, self.peer_call_id)] {
+            let off = self.offset + field;
+            let word = match tcp.get_mut(off..off + 2) {
+                Some(word) => word,
+                None => return csum,
+            };
+            let old = u16::from_be_bytes([word[0], word[1]]);
+            if old != new {
+                word.copy_from_slice(&new.to_be_bytes());
+                csum = update_checksum(csum, old, new);
+            }
+        }
+        csum
+    }
+}
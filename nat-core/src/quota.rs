@@ -0,0 +1,229 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+use super::entry::Protocol;
+use super::table::NatError;
+use heapless::Vec;
+
+/// Hosts with their own quota, unless configured otherwise
+pub const MAX_QUOTA_OVERRIDES: usize = 8;
+
+/// Mappings per internal host unless configured otherwise
+pub const DEFAULT_HOST_QUOTA: u16 = 64;
+
+/// Raw limit, 0 meaning unlimited
+const fn limit(value: u16) -> Option<u16> {
+    if value == 0 {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Limits on the concurrent mappings of one internal host, `None` is
+/// unlimited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub total: Option<u16>,
+    pub tcp: Option<u16>,
+    pub udp: Option<u16>,
+    pub icmp: Option<u16>,
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Self::from_limits(DEFAULT_HOST_QUOTA, 0, 0, 0)
+    }
+}
+
+impl Quota {
+    /// Quota from raw limits, 0 meaning unlimited
+    pub const fn from_limits(total: u16, tcp: u16, udp: u16, icmp: u16) -> Self {
+        Self {
+            total: limit(total),
+            tcp: limit(tcp),
+            udp: limit(udp),
+            icmp: limit(icmp),
+        }
+    }
+
+    fn protocol_limit(&self, proto: Protocol) -> Option<u16> {
+        match proto {
+            Protocol::Tcp => self.tcp,
+            Protocol::Udp => self.udp,
+            Protocol::Icmp => self.icmp,
+            // Only the total applies to the other protocols
+            Protocol::Gre | Protocol::Esp | Protocol::Other(_) => None,
+        }
+    }
+}
+
+/// Live mappings and quota hits of one internal host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HostUsage {
+    pub ip: [u8; 4],
+    /// Mappings per protocol, indexed by `counter()`
+    pub mappings: [u16; 4],
+    /// New sessions refused because of the quota
+    pub hits: u32,
+}
+
+impl HostUsage {
+    fn counter(proto: Protocol) -> usize {
+        match proto {
+            Protocol::Tcp => 0,
+            Protocol::Udp => 1,
+            Protocol::Icmp => 2,
+            Protocol::Gre | Protocol::Esp | Protocol::Other(_) => 3,
+        }
+    }
+
+    pub fn total(&self) -> u16 {
+        self.mappings.iter().sum()
+    }
+
+    pub fn mappings(&self, proto: Protocol) -> u16 {
+        self.mappings[Self::counter(proto)]
+    }
+}
+
+/// Per-host quota overrides and usage accounting
+///
+/// `N` is the NAT table capacity, every entry may belong to a different
+/// host. Up to `Q` hosts have their own quota.
+pub struct QuotaTable<const N: usize, const Q: usize = MAX_QUOTA_OVERRIDES> {
+    overrides: Vec<([u8; 4], Quota), Q>,
+    hosts: Vec<HostUsage, N>,
+}
+
+impl<const N: usize, const Q: usize> Default for QuotaTable<N, Q> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, const Q: usize> QuotaTable<N, Q> {
+    pub const fn new() -> Self {
+        Self {
+            overrides: Vec::new(),
+            hosts: Vec::new(),
+        }
+    }
+
+    /// Set the quota of one host, `None` restores the default
+    pub fn set_override(&mut self, ip: [u8; 4], quota: Option<Quota>) -> Result<(), NatError> {
+        let existing = self.overrides.iter().position(|(host, _)| *host == ip);
+
+        match (existing, quota) {
+            (Some(idx), Some(quota)) => self.overrides[idx].1 = quota,
+            (Some(idx), None) => {
+                self.overrides.swap_remove(idx);
+            }
+            (None, Some(quota)) => self
+                .overrides
+                .push((ip, quota))
+                .map_err(|_| NatError::TableFull)?,
+            (None, None) => {}
+        }
+        Ok(())
+    }
+
+    /// Quota applying to `ip`
+    pub fn quota(&self, ip: &[u8; 4], default: &Quota) -> Quota {
+        self.overrides
+            .iter()
+            .find(|(host, _)| host == ip)
+            .map_or(*default, |(_, quota)| *quota)
+    }
+
+    /// Usage of `ip`, if it has mappings or hit its quota
+    pub fn usage(&self, ip: &[u8; 4]) -> Option<&HostUsage> {
+        self.hosts.iter().find(|h| h.ip == *ip)
+    }
+
+    fn usage_mut(&mut self, ip: &[u8; 4]) -> Option<&mut HostUsage> {
+        if let Some(idx) = self.hosts.iter().position(|h| h.ip == *ip) {
+            return Some(&mut self.hosts[idx]);
+        }
+
+        // Make room by forgetting the idle host with the fewest hits
+        if self.hosts.is_full() {
+            let idx = self
+                .hosts
+                .iter()
+                .enumerate()
+                .filter(|(_, h)| h.total() == 0)
+                .min_by_key(|(_, h)| h.hits)
+                .map(|(idx, _)| idx)?;
+            self.hosts.swap_remove(idx);
+        }
+
+        let _ = self.hosts.push(HostUsage {
+            ip: *ip,
+            mappings: [0; 4],
+            hits: 0,
+        });
+        self.hosts.last_mut()
+    }
+
+    /// Check that `ip` may open one more `proto` mapping, counting a hit
+    /// when it may not
+    pub fn check(
+        &mut self,
+        ip: &[u8; 4],
+        proto: Protocol,
+        default: &Quota,
+    ) -> Result<(), NatError> {
+        let quota = self.quota(ip, default);
+        let (total, mappings) = self
+            .usage(ip)
+            .map_or((0, 0), |h| (h.total(), h.mappings(proto)));
+
+        let over_total = quota.total.is_some_and(|max| total >= max);
+        let over_protocol = quota
+            .protocol_limit(proto)
+            .is_some_and(|max| mappings >= max);
+        if !over_total && !over_protocol {
+            return Ok(());
+        }
+
+        if let Some(usage) = self.usage_mut(ip) {
+            usage.hits = usage.hits.wrapping_add(1);
+            log::warn!(
+                "[NAT] quota: {:?} refused {:?} mapping ({} mappings, {} hits)",
+                ip,
+                proto,
+                total,
+                usage.hits
+            );
+        }
+
+        Err(NatError::QuotaExceeded)
+    }
+
+    /// Account a new mapping of `ip`
+    pub fn add(&mut self, ip: &[u8; 4], proto: Protocol) {
+        if let Some(usage) = self.usage_mut(ip) {
+            let counter = &mut usage.mappings[HostUsage::counter(proto)];
+            *counter = counter.saturating_add(1);
+        }
+    }
+
+    /// Account a removed mapping of `ip`
+    pub fn remove(&mut self, ip: &[u8; 4], proto: Protocol) {
+        let idx = match self.hosts.iter().position(|h| h.ip == *ip) {
+            Some(idx) => idx,
+            None => return,
+        };
+
+        let usage = &mut self.hosts[idx];
+        let counter = &mut usage.mappings[HostUsage::counter(proto)];
+        *counter = counter.saturating_sub(1);
+
+        // Hosts are only remembered while they have mappings or hits
+        if usage.total() == 0 && usage.hits == 0 {
+            self.hosts.swap_remove(idx);
+        }
+    }
+}
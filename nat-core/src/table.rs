@@ -0,0 +1,1753 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+use super::clock::Clock;
+use super::entry::{NatEntry, Protocol, TcpState};
+use super::forward::{ForwardTable, Lease, PortForward, MAX_PORT_FORWARDS};
+use super::fragment::{FragmentKey, FragmentPolicy, FragmentTable};
+use super::index::{endpoint_hash, inbound_hash, outbound_hash, port_hash, HashIndex};
+use super::quota::{HostUsage, Quota, QuotaTable, MAX_QUOTA_OVERRIDES};
+use super::timeout::{TimeoutPolicy, IKE_PORT, IPSEC_NAT_T_PORT, MAX_TIMEOUT_OVERRIDES};
+use crate::packet::{
+    PacketContext, ICMP_ADDR_MASK_REQUEST, ICMP_ECHO_REQUEST, ICMP_INFO_REQUEST,
+    ICMP_TIMESTAMP_REQUEST,
+};
+use crate::pptp::{
+    CALL_CLEAR_REQUEST, CALL_DISCONNECT_NOTIFY, OUTGOING_CALL_REPLY, OUTGOING_CALL_REQUEST,
+    PPTP_PORT, SET_LINK_INFO, WAN_ERROR_NOTIFY,
+};
+use crate::NetIf;
+use heapless::{Deque, Vec};
+
+const PORT_RANGE_START: u16 = 50000;
+const PORT_RANGE_END: u16 = 65535;
+
+/// Ports of the router's own services kept out of the DMZ
+pub const MAX_LOCAL_PORTS: usize = 8;
+
+const DHCP_CLIENT_PORT: u16 = 68;
+
+/// Slots inspected per cleanup pass on the packet path
+const CLEANUP_BATCH: usize = 8;
+
+/// Ended sessions kept until the housekeeping task reports them
+const SESSION_END_QUEUE: usize = 16;
+
+/// NAT translation errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NatError {
+    /// IP protocol not handled by the NAT
+    UnsupportedProtocol,
+    /// No mapping matches the packet
+    NoMapping,
+    /// Every entry slot is in use
+    TableFull,
+    /// Every external port of the range is mapped for this protocol
+    PortsExhausted,
+    /// Port forward overlaps an existing rule or has an invalid range
+    RuleConflict,
+    /// Mapping belongs to another requester
+    NotAuthorized,
+    /// Internal host reached its mapping quota
+    QuotaExceeded,
+    /// Later fragment of a datagram whose first fragment was not translated
+    UnknownFragment,
+    /// Another internal host holds the address-only mapping to the remote
+    RemoteInUse,
+}
+
+/// How external ports are shared between sessions of one internal
+/// endpoint (RFC 4787 section 4.1)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MappingBehavior {
+    /// One external port per internal endpoint, whatever the remote
+    EndpointIndependent,
+    /// One external port per internal endpoint and remote address
+    AddressDependent,
+    /// One external port per session (symmetric NAT)
+    #[default]
+    AddressAndPortDependent,
+}
+
+/// Which remote endpoints may send through an existing mapping
+/// (RFC 4787 section 5)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FilteringBehavior {
+    /// Any remote endpoint
+    EndpointIndependent,
+    /// Remote addresses the internal endpoint has sent to
+    AddressDependent,
+    /// Remote address and port pairs the internal endpoint has sent to
+    #[default]
+    AddressAndPortDependent,
+}
+
+/// Which session gives way when a new one finds the table full
+///
+/// Established TCP sessions are never evicted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Refuse the new session
+    Disabled,
+    /// Least recently used session
+    LeastRecentlyUsed,
+    /// Least recently used half-open or closing TCP session, then the least
+    /// recently used UDP or ICMP one
+    #[default]
+    TransitoryFirst,
+}
+
+/// Why a session left the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEndReason {
+    /// Idle longer than its timeout
+    Expired,
+    /// Removed to make room for a new session
+    Evicted,
+    /// Torn down by its control connection (PPTP calls)
+    Closed,
+}
+
+/// Session removed from the table
+#[derive(Debug, Clone, Copy)]
+pub struct SessionEnd {
+    pub entry: NatEntry,
+    pub reason: SessionEndReason,
+    /// Uptime (ms) the session ended at
+    pub ended_at: u64,
+}
+
+/// Table occupancy and removal counters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatStats {
+    pub current: usize,
+    pub peak: usize,
+    pub capacity: usize,
+    pub expired: u32,
+    pub evicted: u32,
+    /// Session ends lost because the queue was not drained in time
+    pub events_dropped: u32,
+    /// Fragmented datagrams being tracked
+    pub fragment_flows: usize,
+    /// Later fragments that matched no tracked datagram
+    pub fragments_unmatched: u32,
+    /// Live address-only mappings of other IP protocols
+    pub address_only: usize,
+    /// Address-only packets refused because another host held the remote
+    pub address_only_refused: u32,
+}
+
+/// NAT configuration, holding up to `T` per-port timeout overrides
+#[derive(Clone)]
+pub struct NatConfig<const T: usize = MAX_TIMEOUT_OVERRIDES> {
+    /// Internal (LAN) network - packets FROM this network will be NAT'd
+    pub internal_network: [u8; 4],
+    pub internal_netmask: [u8; 4],
+
+    /// External (WAN) IP - our public-facing IP
+    pub external_ip: [u8; 4],
+
+    /// Internal (AP) interface pointer
+    pub internal_iface: *mut NetIf,
+
+    /// External (STA) interface pointer
+    pub external_iface: *mut NetIf,
+
+    /// External port mapping behaviour
+    pub mapping: MappingBehavior,
+
+    /// Inbound filtering behaviour
+    pub filtering: FilteringBehavior,
+
+    /// LAN host receiving unsolicited inbound traffic that matches no
+    /// mapping or port forward. ICMP replies and errors, and traffic to
+    /// `local_ports` or a `local_socket`, still reach the router itself.
+    pub dmz_host: Option<[u8; 4]>,
+
+    /// Ports of the router's own services on the WAN address
+    pub local_ports: Vec<(Protocol, u16), MAX_LOCAL_PORTS>,
+
+    /// Whether the router's stack has a socket open on a local port,
+    /// such as the one its resolver queries from
+    pub local_socket: Option<fn(Protocol, u16) -> bool>,
+
+    /// Loop LAN traffic addressed to `external_ip` back through the
+    /// inbound mappings (RFC 4787 REQ-9)
+    pub hairpinning: bool,
+
+    /// Session evicted when the table is full
+    pub eviction: EvictionPolicy,
+
+    /// Mapping quota of internal hosts without an override
+    pub quota: Quota,
+
+    /// Session idle timeouts
+    pub timeouts: TimeoutPolicy<T>,
+
+    /// Tracking of fragmented datagrams
+    pub fragments: FragmentPolicy,
+}
+
+impl<const T: usize> Default for NatConfig<T> {
+    fn default() -> Self {
+        Self {
+            internal_network: [0; 4],
+            internal_netmask: [0; 4],
+            external_ip: [0; 4],
+            internal_iface: core::ptr::null_mut(),
+            external_iface: core::ptr::null_mut(),
+            mapping: MappingBehavior::default(),
+            filtering: FilteringBehavior::default(),
+            dmz_host: None,
+            local_ports: Vec::from_slice(&[(Protocol::Udp, DHCP_CLIENT_PORT)]).unwrap(),
+            local_socket: None,
+            hairpinning: true,
+            eviction: EvictionPolicy::default(),
+            quota: Quota::default(),
+            timeouts: TimeoutPolicy::default(),
+            fragments: FragmentPolicy::default(),
+        }
+    }
+}
+
+// use core::fmt::Write;
+// use heapless::String;
+// fn format_ip(ip: &[u8; 4]) -> String<16> {
+//     let mut s: String<16> = String::new();
+//     let _ = write!(s, "{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]);
+//     s
+// }
+
+/// NAT session table holding up to `N` sessions, reading the time from `C`
+///
+/// `F` bounds the port forwards and leases, `Q` the hosts with their own
+/// quota and `T` the per-port timeout overrides.
+pub struct NatTable<
+    C: Clock,
+    const N: usize,
+    const F: usize = MAX_PORT_FORWARDS,
+    const Q: usize = MAX_QUOTA_OVERRIDES,
+    const T: usize = MAX_TIMEOUT_OVERRIDES,
+> {
+    /// Entry slots, a slot is live while its `in_use` flag is set
+    entries: [NatEntry; N],
+    /// Unused slot numbers
+    free_slots: Vec<u16, N>,
+    /// Outbound 5-tuple -> slot
+    outbound_index: HashIndex<N>,
+    /// Inbound (remote ip, remote port, external port, proto) -> slot
+    inbound_index: HashIndex<N>,
+    /// (external port, proto) -> slots using it
+    port_index: HashIndex<N>,
+    /// Internal (ip, port, proto) -> slots of that endpoint
+    endpoint_index: HashIndex<N>,
+    /// Static port forwarding rules
+    forwards: ForwardTable<F>,
+    /// Per-host quota overrides and mapping counts
+    quotas: QuotaTable<N, Q>,
+    /// Translations followed by later fragments
+    fragments: FragmentTable,
+    /// Uptime (ms) the current external address was configured at
+    epoch_start: u64,
+    /// Next slot visited by the incremental cleanup
+    cleanup_pos: usize,
+    /// Highest number of live entries since boot
+    peak_usage: usize,
+    /// Sessions evicted to make room for new ones
+    evictions: u32,
+    /// Sessions removed after their idle timeout
+    expirations: u32,
+    /// Ended sessions not yet reported
+    session_ends: Deque<SessionEnd, SESSION_END_QUEUE>,
+    session_ends_dropped: u32,
+    /// Address-only mappings refused, the remote belonging to another host
+    address_only_refused: u32,
+    next_port: u16,
+    config: NatConfig<T>,
+    clock: C,
+}
+
+// The interface pointers are only handed back to the network stack, never
+// dereferenced here, so the table may move to another thread
+unsafe impl<C: Clock + Send, const N: usize, const F: usize, const Q: usize, const T: usize> Send
+    for NatTable<C, N, F, Q, T>
+{
+}
+
+impl<C: Clock, const N: usize, const F: usize, const Q: usize, const T: usize>
+    NatTable<C, N, F, Q, T>
+{
+    pub fn new(config: NatConfig<T>, clock: C) -> Self {
+        // Slot numbers are stored as u16 in the indexes
+        assert!(N > 0 && N < u16::MAX as usize, "unsupported NAT capacity");
+
+        let mut free_slots = Vec::new();
+        for slot in (0..N).rev() {
+            let _ = free_slots.push(slot as u16);
+        }
+
+        Self {
+            entries: [NatEntry::new(); N],
+            free_slots,
+            outbound_index: HashIndex::new(),
+            inbound_index: HashIndex::new(),
+            port_index: HashIndex::new(),
+            endpoint_index: HashIndex::new(),
+            forwards: ForwardTable::new(),
+            quotas: QuotaTable::new(),
+            fragments: FragmentTable::new(),
+            epoch_start: clock.now_ms(),
+            cleanup_pos: 0,
+            peak_usage: 0,
+            evictions: 0,
+            expirations: 0,
+            session_ends: Deque::new(),
+            session_ends_dropped: 0,
+            address_only_refused: 0,
+            next_port: PORT_RANGE_START,
+            config,
+            clock,
+        }
+    }
+
+    /// Number of live entries
+    pub fn len(&self) -> usize {
+        N - self.free_slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of sessions evicted since boot
+    pub fn evictions(&self) -> u32 {
+        self.evictions
+    }
+
+    /// Store entry in a free slot and index it, evicting a session if the
+    /// table is full
+    fn insert_entry(&mut self, entry: NatEntry) -> Result<usize, NatError> {
+        if self.free_slots.is_empty() {
+            self.evict()?;
+        }
+
+        let slot = self.free_slots.pop().ok_or(NatError::TableFull)? as usize;
+
+        self.entries[slot] = entry;
+        self.outbound_index.insert(entry.outbound_hash(), slot);
+        self.inbound_index.insert(entry.inbound_hash(), slot);
+        self.port_index.insert(entry.port_hash(), slot);
+        self.endpoint_index.insert(entry.endpoint_hash(), slot);
+        self.quotas.add(&entry.internal_ip, entry.protocol);
+
+        Ok(slot)
+    }
+
+    /// Unindex entry and return its slot to the free list
+    fn remove_entry(&mut self, slot: usize) {
+        let entries = &self.entries;
+        let entry = &entries[slot];
+        if !entry.in_use {
+            return;
+        }
+
+        self.outbound_index
+            .remove(entry.outbound_hash(), slot, |s| entries[s].outbound_hash());
+        self.inbound_index
+            .remove(entry.inbound_hash(), slot, |s| entries[s].inbound_hash());
+        self.port_index
+            .remove(entry.port_hash(), slot, |s| entries[s].port_hash());
+        self.endpoint_index
+            .remove(entry.endpoint_hash(), slot, |s| entries[s].endpoint_hash());
+        self.quotas.remove(&entry.internal_ip, entry.protocol);
+
+        self.entries[slot].in_use = false;
+        let _ = self.free_slots.push(slot as u16);
+    }
+
+    /// Change the lookup fields of a live entry, returning its slot
+    fn update_entry(&mut self, slot: usize, update: impl FnOnce(&mut NatEntry)) -> usize {
+        let mut entry = self.entries[slot];
+        self.remove_entry(slot);
+        update(&mut entry);
+        // The slot just freed is taken again
+        self.insert_entry(entry).unwrap_or(slot)
+    }
+
+    /// Remove a live session and queue its end
+    fn end_session(&mut self, slot: usize, reason: SessionEndReason, now: u64) {
+        let entry = self.entries[slot];
+        if !entry.in_use {
+            return;
+        }
+
+        self.remove_entry(slot);
+        match reason {
+            SessionEndReason::Expired => self.expirations = self.expirations.wrapping_add(1),
+            SessionEndReason::Evicted => self.evictions = self.evictions.wrapping_add(1),
+            SessionEndReason::Closed => {}
+        }
+
+        // Keep the most recent ends when nobody drains the queue
+        if self.session_ends.is_full() {
+            self.session_ends.pop_front();
+            self.session_ends_dropped = self.session_ends_dropped.wrapping_add(1);
+        }
+        let _ = self.session_ends.push_back(SessionEnd {
+            entry,
+            reason,
+            ended_at: now,
+        });
+    }
+
+    /// Oldest ended session not reported yet
+    pub fn pop_session_end(&mut self) -> Option<SessionEnd> {
+        self.session_ends.pop_front()
+    }
+
+    /// Occupancy and removal counters
+    pub fn stats(&self) -> NatStats {
+        NatStats {
+            current: self.len(),
+            peak: self.peak_usage,
+            capacity: N,
+            expired: self.expirations,
+            evicted: self.evictions,
+            events_dropped: self.session_ends_dropped,
+            fragment_flows: self.fragments.len(),
+            fragments_unmatched: self.fragments.unmatched(),
+            address_only: self
+                .entries
+                .iter()
+                .filter(|e| e.in_use && e.protocol.is_address_only())
+                .count(),
+            address_only_refused: self.address_only_refused,
+        }
+    }
+
+    /// Remove the session chosen by the eviction policy
+    fn evict(&mut self) -> Result<(), NatError> {
+        let now = self.clock.now_ms();
+        let policy = self.config.eviction;
+
+        let victim = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| {
+                e.in_use
+                    && policy != EvictionPolicy::Disabled
+                    && !(e.protocol == Protocol::Tcp && e.tcp_state == TcpState::Established)
+            })
+            .min_by_key(|(_, e)| {
+                let tier = match policy {
+                    EvictionPolicy::TransitoryFirst if e.protocol != Protocol::Tcp => 1,
+                    _ => 0,
+                };
+                (
+                    tier,
+                    core::cmp::Reverse(now.saturating_sub(e.last_activity)),
+                )
+            })
+            .map(|(slot, _)| slot);
+
+        let slot = match victim {
+            Some(slot) => slot,
+            None => {
+                log::error!("[NAT] table full, nothing evictable");
+                return Err(NatError::TableFull);
+            }
+        };
+
+        let entry = self.entries[slot];
+        self.end_session(slot, SessionEndReason::Evicted, now);
+
+        log::warn!(
+            "[NAT] table full, evicted {:?} {:?}:{} -> {:?}:{} ({:?}, idle {} ms, total {})",
+            entry.protocol,
+            entry.internal_ip,
+            entry.internal_port,
+            entry.remote_ip,
+            entry.remote_port,
+            entry.tcp_state,
+            now.saturating_sub(entry.last_activity),
+            self.evictions
+        );
+
+        Ok(())
+    }
+
+    fn update_peak_usage(&mut self) -> usize {
+        let current = self.len();
+
+        if current > self.peak_usage {
+            self.peak_usage = current;
+            log::info!(
+                "[NAT STATS] New Connection: {} / {} (Peak {})",
+                current,
+                N,
+                self.peak_usage
+            );
+        }
+
+        current
+    }
+
+    /// Check if IP is in internal network (should be NAT'd)
+    pub fn is_internal_ip(&self, ip: &[u8; 4]) -> bool {
+        ip.iter()
+            .zip(&self.config.internal_network)
+            .zip(&self.config.internal_netmask)
+            .all(|((ip, net), mask)| ip & mask == net & mask)
+    }
+
+    /// Check if IP is our external IP (WAN interface)
+    fn is_external_ip(&self, ip: &[u8; 4]) -> bool {
+        ip == &self.config.external_ip
+    }
+
+    /// Check whether (external port, proto) is neither used by a live
+    /// mapping nor reserved by a port forward
+    fn is_port_free(&self, port: u16, proto: Protocol) -> bool {
+        !self.forwards.covers(port, proto)
+            && self
+                .port_index
+                .find(port_hash(port, proto), |slot| {
+                    let entry = &self.entries[slot];
+                    entry.external_port == port && entry.protocol == proto
+                })
+                .is_none()
+    }
+
+    /// Allocate a new external port
+    ///
+    /// The client's port is preserved when it lies in the NAT range and is
+    /// free. Otherwise the next free port with the same parity is used
+    /// (RFC 4787 REQ-3/4); the other parity is only handed out once that
+    /// half of the range is used up.
+    fn allocate_port(&mut self, internal_port: u16, proto: Protocol) -> Result<u16, NatError> {
+        if (PORT_RANGE_START..=PORT_RANGE_END).contains(&internal_port)
+            && self.is_port_free(internal_port, proto)
+        {
+            return Ok(internal_port);
+        }
+
+        let range = (PORT_RANGE_END - PORT_RANGE_START) as u32 + 1;
+        let start = (self.next_port - PORT_RANGE_START) as u32;
+        let parity = internal_port & 1;
+
+        for wanted in [parity, parity ^ 1] {
+            for i in 0..range {
+                let port = PORT_RANGE_START + ((start + i) % range) as u16;
+                if port & 1 != wanted || !self.is_port_free(port, proto) {
+                    continue;
+                }
+
+                self.next_port = if port == PORT_RANGE_END {
+                    PORT_RANGE_START
+                } else {
+                    port + 1
+                };
+                return Ok(port);
+            }
+        }
+
+        log::error!("[NAT] port range exhausted for {:?}", proto);
+        Err(NatError::PortsExhausted)
+    }
+
+    /// Find existing NAT entry for outbound packet
+    fn find_outbound(
+        &self,
+        src_ip: &[u8; 4],
+        src_port: u16,
+        dst_ip: &[u8; 4],
+        dst_port: u16,
+        proto: Protocol,
+    ) -> Option<usize> {
+        let hash = outbound_hash(src_ip, src_port, dst_ip, dst_port, proto);
+        self.outbound_index.find(hash, |slot| {
+            self.entries[slot].matches_outbound(src_ip, src_port, dst_ip, dst_port, proto)
+        })
+    }
+
+    /// Find existing NAT entry for inbound packet
+    fn find_inbound(
+        &self,
+        src_ip: &[u8; 4],
+        src_port: u16,
+        dst_port: u16,
+        proto: Protocol,
+    ) -> Option<usize> {
+        let hash = inbound_hash(src_ip, src_port, dst_port, proto);
+        self.inbound_index.find(hash, |slot| {
+            self.entries[slot].matches_inbound(src_ip, src_port, dst_port, proto)
+        })
+    }
+
+    /// Find a session whose external port a new session of the same
+    /// internal endpoint reuses under the configured mapping behaviour
+    fn find_mapping(
+        &self,
+        internal_ip: &[u8; 4],
+        internal_port: u16,
+        remote_ip: &[u8; 4],
+        proto: Protocol,
+    ) -> Option<usize> {
+        let same_remote_ip = match self.config.mapping {
+            MappingBehavior::EndpointIndependent => false,
+            MappingBehavior::AddressDependent => true,
+            MappingBehavior::AddressAndPortDependent => return None,
+        };
+
+        let hash = endpoint_hash(internal_ip, internal_port, proto);
+        self.endpoint_index.find(hash, |slot| {
+            let entry = &self.entries[slot];
+            entry.internal_ip == *internal_ip
+                && entry.internal_port == internal_port
+                && entry.protocol == proto
+                && (!same_remote_ip || entry.remote_ip == *remote_ip)
+        })
+    }
+
+    /// Open a session for an inbound packet from a new remote endpoint if
+    /// the configured filtering behaviour lets it through an existing
+    /// mapping of its destination port
+    fn accept_inbound(&mut self, ctx: &PacketContext, proto: Protocol) -> Option<usize> {
+        let mapping = self.filtered_mapping(&ctx.ip_hdr.src, ctx.dst_port, proto)?;
+
+        self.open_inbound_session(ctx, self.entries[mapping])
+    }
+
+    /// Mapping of `external_port` that the filtering behaviour opens to
+    /// `remote_ip`
+    fn filtered_mapping(
+        &self,
+        remote_ip: &[u8; 4],
+        external_port: u16,
+        proto: Protocol,
+    ) -> Option<usize> {
+        let same_remote_ip = match self.config.filtering {
+            FilteringBehavior::EndpointIndependent => false,
+            FilteringBehavior::AddressDependent => true,
+            FilteringBehavior::AddressAndPortDependent => return None,
+        };
+
+        // ICMP queries are never opened from outside, the other protocols
+        // have no port to share
+        if !matches!(proto, Protocol::Tcp | Protocol::Udp) {
+            return None;
+        }
+
+        self.port_index
+            .find(port_hash(external_port, proto), |slot| {
+                let entry = &self.entries[slot];
+                entry.external_port == external_port
+                    && entry.protocol == proto
+                    && (!same_remote_ip || entry.remote_ip == *remote_ip)
+            })
+    }
+
+    /// Open a session for an inbound packet matching a port forward
+    fn forward_inbound(&mut self, ctx: &PacketContext, proto: Protocol) -> Option<usize> {
+        let (internal_ip, internal_port) = self.forwards.find_inbound(ctx.dst_port, proto)?;
+        let entry = self.static_entry(internal_ip, internal_port, ctx.dst_port, proto);
+
+        self.open_inbound_session(ctx, entry)
+    }
+
+    /// Open a session towards the DMZ host for unsolicited inbound traffic
+    fn dmz_inbound(&mut self, ctx: &PacketContext, proto: Protocol) -> Option<usize> {
+        let dmz_host = self.config.dmz_host?;
+
+        if self.is_local_traffic(ctx, proto) {
+            return None;
+        }
+
+        // Ports (and ICMP identifiers) are passed through unchanged
+        let entry = self.static_entry(dmz_host, ctx.dst_port, ctx.dst_port, proto);
+
+        self.open_inbound_session(ctx, entry)
+    }
+
+    /// Inbound traffic for the router's own stack rather than the DMZ host
+    fn is_local_traffic(&self, ctx: &PacketContext, proto: Protocol) -> bool {
+        // Requests go to the DMZ, replies without a mapping answer the
+        // router's own queries
+        if proto == Protocol::Icmp {
+            return !matches!(
+                ctx.icmp_type,
+                ICMP_ECHO_REQUEST
+                    | ICMP_TIMESTAMP_REQUEST
+                    | ICMP_INFO_REQUEST
+                    | ICMP_ADDR_MASK_REQUEST
+            );
+        }
+
+        self.config.local_ports.contains(&(proto, ctx.dst_port))
+            || self
+                .config
+                .local_socket
+                .is_some_and(|bound| bound(proto, ctx.dst_port))
+    }
+
+    /// Entry template for a statically configured internal endpoint
+    fn static_entry(
+        &self,
+        internal_ip: [u8; 4],
+        internal_port: u16,
+        external_port: u16,
+        proto: Protocol,
+    ) -> NatEntry {
+        let mut entry = NatEntry::new();
+        entry.internal_ip = internal_ip;
+        entry.internal_port = internal_port;
+        entry.external_ip = self.config.external_ip;
+        entry.external_port = external_port;
+        entry.protocol = proto;
+        entry.internal_iface = self.config.internal_iface;
+        entry.external_iface = self.config.external_iface;
+        entry
+    }
+
+    /// Add the session of an inbound packet, using the internal endpoint,
+    /// external port and interfaces of `entry`
+    fn open_inbound_session(&mut self, ctx: &PacketContext, mut entry: NatEntry) -> Option<usize> {
+        entry.remote_ip = ctx.ip_hdr.src;
+        entry.remote_port = ctx.src_port;
+        entry.tcp_state = TcpState::initial(ctx.tcp_flags);
+        entry.created = self.clock.now_ms();
+        entry.last_activity = entry.created;
+        entry.in_use = true;
+
+        self.cleanup();
+        let slot = self.insert_entry(entry).ok()?;
+        self.update_peak_usage();
+
+        Some(slot)
+    }
+
+    /// Remove expired entries among the next `count` slots
+    fn sweep(&mut self, count: usize, now: u64) {
+        for _ in 0..count {
+            let slot = self.cleanup_pos;
+            self.cleanup_pos = (self.cleanup_pos + 1) % N;
+
+            let entry = &self.entries[slot];
+            if entry.in_use && entry.is_expired(now, self.config.timeouts.timeout_ms(entry)) {
+                self.end_session(slot, SessionEndReason::Expired, now);
+            }
+        }
+    }
+
+    /// Clean up expired entries
+    ///
+    /// Only a small batch of slots is visited per call so the per-packet
+    /// cost stays flat; the whole table is swept when no slot is free.
+    fn cleanup(&mut self) {
+        let now = self.clock.now_ms();
+        let before = self.len();
+
+        self.sweep(CLEANUP_BATCH, now);
+        if self.free_slots.is_empty() {
+            self.sweep(N, now);
+        }
+
+        let leases = self.forwards.expire_leases(now);
+        if leases > 0 {
+            log::info!("[NAT] cleanup: {} port mapping lease(s) expired", leases);
+        }
+        self.fragments.expire(now);
+
+        let after = self.len();
+        let removed = before - after;
+
+        if removed > 0 {
+            log::error!(
+                "[NAT] cleanup: {} expired entry removed. ({} -> {})",
+                removed,
+                before,
+                after
+            );
+        }
+    }
+
+    /// Periodic maintenance off the packet path
+    ///
+    /// Sweeps the whole table and the leases, so an idle router still
+    /// frees its slots, and refreshes the peak occupancy.
+    pub fn housekeeping(&mut self) -> NatStats {
+        let now = self.clock.now_ms();
+
+        self.sweep(N, now);
+        let leases = self.forwards.expire_leases(now);
+        if leases > 0 {
+            log::info!(
+                "[NAT] housekeeping: {} port mapping lease(s) expired",
+                leases
+            );
+        }
+        self.fragments.expire(now);
+        self.update_peak_usage();
+
+        self.stats()
+    }
+
+    /// Translate outbound packet (LAN -> WAN)
+    /// Only translate if source is from internal network
+    pub fn translate_outbound(&mut self, ctx: &mut PacketContext) -> Result<(), NatError> {
+        let src_internal = self.is_internal_ip(&ctx.ip_hdr.src);
+        let dst_internal = self.is_internal_ip(&ctx.ip_hdr.dst);
+
+        // Policy checks
+        if !src_internal {
+            log::info!("[NAT OUT] ✓ PASS-THROUGH: Source not internal");
+            return Ok(());
+        }
+
+        if dst_internal {
+            log::info!("[NAT OUT] ✓ PASS-THROUGH: Destination is internal");
+            return Ok(());
+        }
+
+        let dst = ctx.ip_hdr.dst;
+
+        // Multicast (224.0.0.0 – 239.255.255.255)
+        if (224..=239).contains(&dst[0]) {
+            log::warn!("[NAT OUT] SKIP: Multicast packet");
+            return Ok(());
+        }
+
+        // Global broadcast
+        if dst == [255, 255, 255, 255] {
+            log::warn!("[NAT OUT] SKIP: Global broadcast");
+            return Ok(());
+        }
+
+        // Local broadcast (x.x.x.255)
+        // LAN broadcast (exam: 192.168.4.255)
+        let lan_ip = [192, 168, 4, 1];
+        let mut broadcast = [0u8; 4];
+        for i in 0..4 {
+            broadcast[i] =
+                (lan_ip[i] & self.config.internal_netmask[i]) | (!self.config.internal_netmask[i]);
+        }
+
+        if dst == broadcast {
+            log::warn!("[NAT OUT] SKIP LAN broadcast");
+            return Ok(());
+        }
+
+        let proto = ctx.protocol();
+
+        if ctx.is_later_fragment() {
+            if self.is_external_ip(&dst) && !self.hairpins(proto) {
+                return Ok(());
+            }
+            return self.translate_fragment(ctx);
+        }
+
+        let key = FragmentKey::of(ctx);
+
+        // ICMP errors follow the mapping of the packet they quote
+        if ctx.inner.is_some() {
+            self.translate_icmp_error_outbound(ctx)?;
+        } else if self.is_external_ip(&dst) {
+            self.translate_hairpin(ctx, proto)?;
+        } else if proto == Protocol::Esp {
+            self.translate_esp_outbound(ctx)?;
+        } else {
+            self.pptp_outbound(ctx)?;
+            self.translate_outbound_session(ctx, proto)?;
+        }
+
+        self.track_fragment(key, ctx);
+        Ok(())
+    }
+
+    /// LAN packets to the external address are looped back to the LAN
+    fn hairpins(&self, proto: Protocol) -> bool {
+        self.config.hairpinning && matches!(proto, Protocol::Tcp | Protocol::Udp)
+    }
+
+    /// Remember the translation of a first fragment for the later ones
+    fn track_fragment(&mut self, key: FragmentKey, ctx: &PacketContext) {
+        if ctx.is_fragment() && ctx.needs_update {
+            let now = self.clock.now_ms();
+            self.fragments.track(key, ctx, now, &self.config.fragments);
+        }
+    }
+
+    /// Translate a later fragment like the first one of its datagram
+    ///
+    /// Later fragments carry no ports, so they can only follow the
+    /// translation recorded for their first fragment. Fragments are not
+    /// held back: one overtaking the first fragment of its datagram is
+    /// refused untouched and left to the sender's retransmission.
+    fn translate_fragment(&mut self, ctx: &mut PacketContext) -> Result<(), NatError> {
+        let now = self.clock.now_ms();
+
+        if !self.fragments.translate(ctx, now, &self.config.fragments) {
+            log::warn!(
+                "[NAT] fragment {:?} -> {:?} id {} without its first fragment",
+                ctx.ip_hdr.src,
+                ctx.ip_hdr.dst,
+                u16::from_be_bytes(ctx.ip_hdr.id)
+            );
+            return Err(NatError::UnknownFragment);
+        }
+
+        Ok(())
+    }
+
+    /// Translate a LAN packet addressed to our external address
+    ///
+    /// The source gets its outbound mapping as if the packet left through
+    /// the WAN, the destination is then resolved through the inbound
+    /// mappings and the packet goes back out the AP interface. The server
+    /// sees the client's external endpoint (RFC 4787 REQ-9).
+    fn translate_hairpin(
+        &mut self,
+        ctx: &mut PacketContext,
+        proto: Protocol,
+    ) -> Result<(), NatError> {
+        // Pings and everything else for the router itself stay local
+        if !self.hairpins(proto) {
+            log::info!("[NAT OUT] ✓ PASS-THROUGH: Destination is the external address");
+            return Ok(());
+        }
+
+        // Without a target the packet is for the router, the source gets
+        // no session for it
+        if !self.has_hairpin_target(ctx, proto) {
+            log::info!(
+                "[NAT OUT] ✓ PASS-THROUGH: no hairpin mapping for external port {}",
+                ctx.dst_port
+            );
+            return Ok(());
+        }
+
+        self.translate_outbound_session(ctx, proto)?;
+
+        if let Err(e) = self.translate_inbound_packet(ctx, proto) {
+            log::warn!(
+                "[NAT OUT] HAIRPIN: no mapping for external port {}",
+                ctx.dst_port
+            );
+            return Err(e);
+        }
+
+        log::info!(
+            "[NAT OUT] HAIRPIN: {:?}:{} -> {:?}:{}",
+            ctx.ip_hdr.src,
+            ctx.src_port,
+            ctx.ip_hdr.dst,
+            ctx.dst_port
+        );
+
+        Ok(())
+    }
+
+    /// Whether a LAN packet to the external address would find its way
+    /// through a port forward, lease or mapping once its source is mapped
+    fn has_hairpin_target(&self, ctx: &PacketContext, proto: Protocol) -> bool {
+        if self.forwards.find_inbound(ctx.dst_port, proto).is_some() {
+            return true;
+        }
+
+        let external_ip = self.config.external_ip;
+
+        // Session opened by an earlier hairpinned packet of the source
+        let session = self
+            .find_outbound(
+                &ctx.ip_hdr.src,
+                ctx.src_port,
+                &ctx.ip_hdr.dst,
+                ctx.dst_port,
+                proto,
+            )
+            .and_then(|idx| {
+                let entry = &self.entries[idx];
+                self.find_inbound(&external_ip, entry.external_port, ctx.dst_port, proto)
+            });
+
+        session.is_some()
+            || self
+                .filtered_mapping(&external_ip, ctx.dst_port, proto)
+                .is_some()
+    }
+
+    /// Map the source of an outbound packet, reusing or creating its session
+    fn translate_outbound_session(
+        &mut self,
+        ctx: &mut PacketContext,
+        proto: Protocol,
+    ) -> Result<(), NatError> {
+        // Check if we already have an entry
+        if let Some(idx) = self.find_outbound(
+            &ctx.ip_hdr.src,
+            ctx.src_port,
+            &ctx.ip_hdr.dst,
+            ctx.dst_port,
+            proto,
+        ) {
+            // Update existing entry
+            let entry = &mut self.entries[idx];
+            entry.touch(self.clock.now_ms());
+            entry.track_tcp(ctx.tcp_flags, true);
+
+            // Translate
+            ctx.ip_hdr.src = entry.external_ip;
+            ctx.src_port = entry.external_port;
+
+            // *** CHANGE INTERFACE ***
+            if !entry.external_iface.is_null() {
+                ctx.iface = entry.external_iface;
+            }
+
+            ctx.needs_update = true;
+
+            return Ok(());
+        }
+
+        // Create new entry
+        self.cleanup(); // Make room if needed
+
+        // GRE calls are only opened by the PPTP control connection
+        if proto == Protocol::Gre {
+            log::warn!(
+                "[NAT OUT] GRE call {} from {:?} without PPTP control connection",
+                ctx.dst_port,
+                ctx.ip_hdr.src
+            );
+            return Err(NatError::NoMapping);
+        }
+
+        if proto.is_address_only() {
+            self.check_remote_free(ctx, proto)?;
+        }
+
+        self.quotas
+            .check(&ctx.ip_hdr.src, proto, &self.config.quota)?;
+
+        // ICMP query identifiers are allocated like ports so that hosts
+        // pinging the same remote get distinct mappings
+        // Forwarded servers answer from their public port
+        let forwarded = self
+            .forwards
+            .find_outbound(&ctx.ip_hdr.src, ctx.src_port, proto);
+        let mapping = self.find_mapping(&ctx.ip_hdr.src, ctx.src_port, &ctx.ip_hdr.dst, proto);
+
+        let external_port = match (forwarded, mapping) {
+            _ if proto.is_address_only() => 0,
+            (Some(port), _) => port,
+            (None, Some(idx)) => self.entries[idx].external_port,
+            (None, None) if self.keeps_ike_port(ctx, proto) => ctx.src_port,
+            (None, None) => self.allocate_port(ctx.src_port, proto)?,
+        };
+
+        let external_ip = self.config.external_ip;
+
+        let mut entry = NatEntry::new();
+        entry.internal_ip = ctx.ip_hdr.src;
+        entry.internal_port = ctx.src_port;
+        entry.external_ip = external_ip;
+        entry.external_port = external_port;
+        entry.remote_ip = ctx.ip_hdr.dst;
+        entry.remote_port = ctx.dst_port;
+        entry.protocol = proto;
+        entry.tcp_state = TcpState::initial(ctx.tcp_flags);
+        entry.created = self.clock.now_ms();
+        entry.last_activity = entry.created;
+        entry.in_use = true;
+
+        // *** STORE INTERFACE POINTERS ***
+        entry.internal_iface = ctx.orig_iface;
+        entry.external_iface = self.config.external_iface;
+
+        // Add to table
+        self.insert_entry(entry)?;
+
+        // Translate packet
+        ctx.ip_hdr.src = external_ip;
+        ctx.src_port = external_port;
+
+        // *** CHANGE INTERFACE ***
+        if !self.config.external_iface.is_null() {
+            ctx.iface = self.config.external_iface;
+        } else {
+            log::error!("[NAT OUT] External interface not configured!");
+        }
+
+        ctx.needs_update = true;
+
+        let current_usage = self.update_peak_usage();
+        log::info!(
+            "[NAT OUT] Online Connection: {} / {} (Peak: {})",
+            current_usage,
+            N,
+            self.peak_usage
+        );
+
+        Ok(())
+    }
+
+    /// Refuse an address-only mapping to a remote another internal host
+    /// already has one with, replies could not tell them apart
+    fn check_remote_free(&mut self, ctx: &PacketContext, proto: Protocol) -> Result<(), NatError> {
+        let idx = match self.find_inbound(&ctx.ip_hdr.dst, 0, 0, proto) {
+            Some(idx) => idx,
+            None => return Ok(()),
+        };
+
+        self.address_only_refused = self.address_only_refused.wrapping_add(1);
+        log::warn!(
+            "[NAT OUT] {:?} to {:?} from {:?} refused, mapped for {:?}",
+            proto,
+            ctx.ip_hdr.dst,
+            ctx.ip_hdr.src,
+            self.entries[idx].internal_ip
+        );
+        Err(NatError::RemoteInUse)
+    }
+
+    /// Translate inbound packet (WAN -> LAN)
+    /// Only translate if destination is our external IP
+    pub fn translate_inbound(&mut self, ctx: &mut PacketContext) -> Result<(), NatError> {
+        if !self.is_external_ip(&ctx.ip_hdr.dst) {
+            // Not for us, don't NAT
+            return Ok(());
+        }
+
+        // LAN hosts reaching the external address are hairpinned on the
+        // outbound path, their source has to be mapped first
+        if self.is_internal_ip(&ctx.ip_hdr.src) {
+            return Ok(());
+        }
+
+        let proto = ctx.protocol();
+
+        if ctx.is_later_fragment() {
+            return self.translate_fragment(ctx);
+        }
+
+        let key = FragmentKey::of(ctx);
+        self.translate_inbound_packet(ctx, proto)?;
+        self.track_fragment(key, ctx);
+
+        Ok(())
+    }
+
+    /// Translate an inbound packet addressed to the external address
+    fn translate_inbound_packet(
+        &mut self,
+        ctx: &mut PacketContext,
+        proto: Protocol,
+    ) -> Result<(), NatError> {
+        // ICMP errors follow the mapping of the packet they quote
+        if ctx.inner.is_some() {
+            return self.translate_icmp_error_inbound(ctx);
+        }
+
+        if proto == Protocol::Esp {
+            return self.translate_esp_inbound(ctx);
+        }
+
+        // Find matching entry, or one the filtering behaviour allows
+        let idx = match self.find_inbound(&ctx.ip_hdr.src, ctx.src_port, ctx.dst_port, proto) {
+            Some(idx) => idx,
+            None => self
+                .forward_inbound(ctx, proto)
+                .or_else(|| self.accept_inbound(ctx, proto))
+                .or_else(|| self.dmz_inbound(ctx, proto))
+                .ok_or(NatError::NoMapping)?,
+        };
+
+        let entry = &mut self.entries[idx];
+        entry.touch(self.clock.now_ms());
+        entry.track_tcp(ctx.tcp_flags, false);
+
+        // Translate destination IP and port
+        ctx.ip_hdr.dst = entry.internal_ip;
+        ctx.dst_port = entry.internal_port;
+
+        // *** CHANGE INTERFACE ***
+        if !entry.internal_iface.is_null() {
+            ctx.iface = entry.internal_iface;
+        }
+
+        ctx.needs_update = true;
+
+        self.pptp_inbound(ctx);
+
+        Ok(())
+    }
+
+    /// IKE between the well-known ports keeps its source port unless
+    /// another host already uses it towards the same remote. Peers
+    /// without NAT traversal only accept IKE from port 500.
+    fn keeps_ike_port(&self, ctx: &PacketContext, proto: Protocol) -> bool {
+        let port = ctx.src_port;
+        if proto != Protocol::Udp
+            || !matches!(port, IKE_PORT | IPSEC_NAT_T_PORT)
+            || ctx.dst_port != port
+            || self.forwards.covers(port, proto)
+        {
+            return false;
+        }
+
+        self.port_index
+            .find(port_hash(port, proto), |slot| {
+                let entry = &self.entries[slot];
+                entry.external_port == port
+                    && entry.protocol == proto
+                    && entry.remote_ip == ctx.ip_hdr.dst
+            })
+            .is_none()
+    }
+
+    /// Swap the call ID a LAN PPTP client announces for an external one
+    fn pptp_outbound(&mut self, ctx: &mut PacketContext) -> Result<(), NatError> {
+        let mut msg = match ctx.pptp {
+            Some(msg) if ctx.dst_port == PPTP_PORT => msg,
+            _ => return Ok(()),
+        };
+
+        match msg.kind {
+            OUTGOING_CALL_REQUEST => msg.call_id = self.open_pptp_call(ctx, msg.call_id)?,
+            CALL_CLEAR_REQUEST => {
+                let idx = self.find_pptp_call(&ctx.ip_hdr.src, msg.call_id, &ctx.ip_hdr.dst);
+                match idx {
+                    Some(idx) => msg.call_id = self.entries[idx].external_port,
+                    None => return Ok(()),
+                }
+            }
+            _ => return Ok(()),
+        }
+
+        ctx.pptp = Some(msg);
+        Ok(())
+    }
+
+    /// Translate the call IDs of a PPTP server message to a LAN client
+    fn pptp_inbound(&mut self, ctx: &mut PacketContext) {
+        let mut msg = match ctx.pptp {
+            Some(msg) if ctx.src_port == PPTP_PORT => msg,
+            _ => return,
+        };
+        let remote_ip = ctx.ip_hdr.src;
+
+        match msg.kind {
+            OUTGOING_CALL_REPLY => {
+                let idx = match self.find_inbound(&remote_ip, 0, msg.peer_call_id, Protocol::Gre) {
+                    Some(idx) => idx,
+                    None => return,
+                };
+                // The server's call ID is in the GRE the client sends
+                let server_call_id = msg.call_id;
+                let idx = self.update_entry(idx, |entry| entry.remote_port = server_call_id);
+                msg.peer_call_id = self.entries[idx].internal_port;
+            }
+            WAN_ERROR_NOTIFY | SET_LINK_INFO => {
+                match self.find_inbound(&remote_ip, 0, msg.call_id, Protocol::Gre) {
+                    Some(idx) => msg.call_id = self.entries[idx].internal_port,
+                    None => return,
+                }
+            }
+            CALL_DISCONNECT_NOTIFY => {
+                let call =
+                    self.find_outbound(&ctx.ip_hdr.dst, 0, &remote_ip, msg.call_id, Protocol::Gre);
+                if let Some(idx) = call {
+                    let now = self.clock.now_ms();
+                    self.end_session(idx, SessionEndReason::Closed, now);
+                }
+                return;
+            }
+            _ => return,
+        }
+
+        ctx.pptp = Some(msg);
+    }
+
+    /// GRE mapping of the call `call_id` of a LAN client
+    fn find_pptp_call(
+        &self,
+        internal_ip: &[u8; 4],
+        call_id: u16,
+        remote_ip: &[u8; 4],
+    ) -> Option<usize> {
+        self.endpoint_index
+            .find(endpoint_hash(internal_ip, call_id, Protocol::Gre), |slot| {
+                let entry = &self.entries[slot];
+                entry.internal_ip == *internal_ip
+                    && entry.internal_port == call_id
+                    && entry.protocol == Protocol::Gre
+                    && entry.remote_ip == *remote_ip
+            })
+    }
+
+    /// Map the call ID of an Outgoing-Call-Request, returning the
+    /// external call ID
+    ///
+    /// The server's call ID is learned from its reply.
+    fn open_pptp_call(&mut self, ctx: &PacketContext, call_id: u16) -> Result<u16, NatError> {
+        if let Some(idx) = self.find_pptp_call(&ctx.ip_hdr.src, call_id, &ctx.ip_hdr.dst) {
+            return Ok(self.entries[idx].external_port);
+        }
+
+        self.cleanup();
+        self.quotas
+            .check(&ctx.ip_hdr.src, Protocol::Gre, &self.config.quota)?;
+        let external_call_id = self.allocate_port(call_id, Protocol::Gre)?;
+
+        let mut entry = self.static_entry(ctx.ip_hdr.src, call_id, external_call_id, Protocol::Gre);
+        entry.remote_ip = ctx.ip_hdr.dst;
+        entry.internal_iface = ctx.orig_iface;
+        entry.created = self.clock.now_ms();
+        entry.last_activity = entry.created;
+        entry.in_use = true;
+        self.insert_entry(entry)?;
+        self.update_peak_usage();
+
+        log::info!(
+            "[NAT OUT] PPTP call {} of {:?} to {:?} mapped to {}",
+            call_id,
+            ctx.ip_hdr.src,
+            ctx.ip_hdr.dst,
+            external_call_id
+        );
+
+        Ok(external_call_id)
+    }
+
+    /// Map the source of outbound ESP, one entry per SA
+    fn translate_esp_outbound(&mut self, ctx: &mut PacketContext) -> Result<(), NatError> {
+        // SPI 0 is reserved (RFC 4303), it also marks unlearned SAs
+        if ctx.spi == 0 {
+            return Err(NatError::NoMapping);
+        }
+
+        let hash = outbound_hash(&ctx.ip_hdr.src, 0, &ctx.ip_hdr.dst, 0, Protocol::Esp);
+        let found = self.outbound_index.find(hash, |slot| {
+            let entry = &self.entries[slot];
+            entry.matches_outbound(&ctx.ip_hdr.src, 0, &ctx.ip_hdr.dst, 0, Protocol::Esp)
+                && entry.spi_out == ctx.spi
+        });
+
+        let idx = match found {
+            Some(idx) => idx,
+            None => {
+                self.cleanup();
+                self.quotas
+                    .check(&ctx.ip_hdr.src, Protocol::Esp, &self.config.quota)?;
+
+                let mut entry = self.static_entry(ctx.ip_hdr.src, 0, 0, Protocol::Esp);
+                entry.remote_ip = ctx.ip_hdr.dst;
+                entry.spi_out = ctx.spi;
+                entry.internal_iface = ctx.orig_iface;
+                entry.created = self.clock.now_ms();
+                entry.last_activity = entry.created;
+                entry.in_use = true;
+                let idx = self.insert_entry(entry)?;
+                self.update_peak_usage();
+
+                log::info!(
+                    "[NAT OUT] ESP SPI {:#010x} {:?} -> {:?}",
+                    ctx.spi,
+                    ctx.ip_hdr.src,
+                    ctx.ip_hdr.dst
+                );
+                idx
+            }
+        };
+
+        let entry = &mut self.entries[idx];
+        entry.touch(self.clock.now_ms());
+        ctx.ip_hdr.src = entry.external_ip;
+        if !entry.external_iface.is_null() {
+            ctx.iface = entry.external_iface;
+        }
+        ctx.needs_update = true;
+
+        Ok(())
+    }
+
+    /// Map the destination of inbound ESP through its SA
+    ///
+    /// The SPI the remote uses towards a LAN host is unknown until its
+    /// first packet, which is paired with an outbound SA to the same
+    /// remote still waiting for it.
+    fn translate_esp_inbound(&mut self, ctx: &mut PacketContext) -> Result<(), NatError> {
+        if ctx.spi == 0 {
+            return Err(NatError::NoMapping);
+        }
+
+        let remote_ip = ctx.ip_hdr.src;
+        let hash = inbound_hash(&remote_ip, 0, 0, Protocol::Esp);
+        let find = |table: &Self, spi: u32, skip: Option<usize>| {
+            table.inbound_index.find(hash, |slot| {
+                let entry = &table.entries[slot];
+                entry.matches_inbound(&remote_ip, 0, 0, Protocol::Esp)
+                    && entry.spi_in == spi
+                    && Some(slot) != skip
+            })
+        };
+
+        let idx = match find(self, ctx.spi, None) {
+            Some(idx) => idx,
+            None => {
+                // A new SA is only paired when a single host is waiting for
+                // one from this remote, the SPI does not tell hosts apart
+                let idx = find(self, 0, None).ok_or(NatError::NoMapping)?;
+                if find(self, 0, Some(idx)).is_some() {
+                    log::warn!(
+                        "[NAT] ESP SPI {:#010x} from {:?} dropped, several hosts await an SA",
+                        ctx.spi,
+                        remote_ip
+                    );
+                    return Err(NatError::NoMapping);
+                }
+
+                self.entries[idx].spi_in = ctx.spi;
+                log::info!(
+                    "[NAT] ESP SPI {:#010x} from {:?} paired with {:#010x} of {:?}",
+                    ctx.spi,
+                    remote_ip,
+                    self.entries[idx].spi_out,
+                    self.entries[idx].internal_ip
+                );
+                idx
+            }
+        };
+
+        let entry = &mut self.entries[idx];
+        entry.touch(self.clock.now_ms());
+        ctx.ip_hdr.dst = entry.internal_ip;
+        if !entry.internal_iface.is_null() {
+            ctx.iface = entry.internal_iface;
+        }
+        ctx.needs_update = true;
+
+        Ok(())
+    }
+
+    /// Translate ICMP error sent by a LAN host about a WAN packet
+    ///
+    /// The quoted packet went remote -> internal, so it is looked up as an
+    /// outbound flow with its addresses swapped (RFC 5508 REQ-4).
+    fn translate_icmp_error_outbound(&mut self, ctx: &mut PacketContext) -> Result<(), NatError> {
+        let mut inner = ctx.inner.ok_or(NatError::NoMapping)?;
+        let proto = Protocol::from_ip(inner.proto);
+
+        let idx = self
+            .find_outbound(
+                &inner.dst,
+                inner.dst_port,
+                &inner.src,
+                inner.src_port,
+                proto,
+            )
+            .ok_or(NatError::NoMapping)?;
+        let entry = &self.entries[idx];
+
+        ctx.ip_hdr.src = entry.external_ip;
+        inner.dst = entry.external_ip;
+        inner.dst_port = entry.external_port;
+        ctx.inner = Some(inner);
+
+        if !entry.external_iface.is_null() {
+            ctx.iface = entry.external_iface;
+        }
+
+        ctx.needs_update = true;
+
+        Ok(())
+    }
+
+    /// Translate ICMP error sent by a WAN router about one of our packets
+    ///
+    /// The quoted packet is the already translated outbound one, so its
+    /// destination and source port form the inbound lookup key.
+    fn translate_icmp_error_inbound(&mut self, ctx: &mut PacketContext) -> Result<(), NatError> {
+        let mut inner = ctx.inner.ok_or(NatError::NoMapping)?;
+        let proto = Protocol::from_ip(inner.proto);
+
+        if !self.is_external_ip(&inner.src) {
+            return Err(NatError::NoMapping);
+        }
+
+        let idx = self
+            .find_inbound(&inner.dst, inner.dst_port, inner.src_port, proto)
+            .ok_or(NatError::NoMapping)?;
+        let entry = &self.entries[idx];
+
+        ctx.ip_hdr.dst = entry.internal_ip;
+        inner.src = entry.internal_ip;
+        inner.src_port = entry.internal_port;
+        ctx.inner = Some(inner);
+
+        if !entry.internal_iface.is_null() {
+            ctx.iface = entry.internal_iface;
+        }
+
+        ctx.needs_update = true;
+
+        Ok(())
+    }
+
+    /// Add a static port forward
+    pub fn add_port_forward(&mut self, rule: PortForward) -> Result<(), NatError> {
+        self.forwards.add(rule)?;
+        log::info!(
+            "[NAT] port forward {:?} {}-{} -> {:?}:{}",
+            rule.protocol,
+            rule.external_start,
+            rule.external_end,
+            rule.internal_ip,
+            rule.internal_port
+        );
+        Ok(())
+    }
+
+    /// Remove the port forward starting at `external_start`
+    ///
+    /// Sessions already opened through the rule run until they expire.
+    pub fn remove_port_forward(&mut self, proto: Protocol, external_start: u16) -> bool {
+        self.forwards.remove(proto, external_start).is_some()
+    }
+
+    /// Create or refresh a leased port forward for a LAN client
+    ///
+    /// `suggested_port` is used when free, otherwise a port is allocated
+    /// unless `require_suggested` is set. Returns the external port.
+    #[allow(clippy::too_many_arguments)]
+    pub fn map_lease(
+        &mut self,
+        internal_ip: [u8; 4],
+        internal_port: u16,
+        proto: Protocol,
+        suggested_port: u16,
+        lifetime_s: u32,
+        nonce: [u8; 12],
+        require_suggested: bool,
+    ) -> Result<u16, NatError> {
+        let expires = self.clock.now_ms().saturating_add(lifetime_s as u64 * 1000);
+
+        if let Some(rule) = self
+            .forwards
+            .find_lease_mut(&internal_ip, internal_port, proto)
+        {
+            if rule.lease.map(|l| l.nonce) != Some(nonce) {
+                return Err(NatError::NotAuthorized);
+            }
+            rule.lease = Some(Lease { expires, nonce });
+            return Ok(rule.external_start);
+        }
+
+        let external_port = if suggested_port >= 1024 && self.is_port_free(suggested_port, proto) {
+            suggested_port
+        } else if require_suggested {
+            return Err(NatError::RuleConflict);
+        } else {
+            self.allocate_port(internal_port, proto)?
+        };
+
+        self.forwards.add(PortForward {
+            protocol: proto,
+            external_start: external_port,
+            external_end: external_port,
+            internal_ip,
+            internal_port,
+            lease: Some(Lease { expires, nonce }),
+        })?;
+
+        log::info!(
+            "[NAT] lease {:?} {} -> {:?}:{} ({} s)",
+            proto,
+            external_port,
+            internal_ip,
+            internal_port,
+            lifetime_s
+        );
+
+        Ok(external_port)
+    }
+
+    /// Delete leased port forwards of a LAN client, all its ports when
+    /// `internal_port` is `None` and all protocols when `proto` is `None`
+    ///
+    /// PCP deletes pass the MAP nonce, leases requested with another one
+    /// are refused with `NotAuthorized`.
+    pub fn unmap_lease(
+        &mut self,
+        internal_ip: [u8; 4],
+        internal_port: Option<u16>,
+        proto: Option<Protocol>,
+        nonce: Option<[u8; 12]>,
+    ) -> Result<usize, NatError> {
+        self.forwards
+            .remove_leases(&internal_ip, internal_port, proto, nonce)
+    }
+
+    /// Delete the leased port forward on `external_port`, only its owner
+    /// may remove it
+    pub fn unmap_lease_external(
+        &mut self,
+        internal_ip: [u8; 4],
+        external_port: u16,
+        proto: Protocol,
+    ) -> Result<(), NatError> {
+        let rule = self
+            .forwards
+            .find_lease(external_port, proto)
+            .ok_or(NatError::NoMapping)?;
+
+        if rule.internal_ip != internal_ip {
+            return Err(NatError::NotAuthorized);
+        }
+
+        let start = rule.external_start;
+        self.forwards.remove(proto, start);
+        Ok(())
+    }
+
+    /// Leased port forward of a LAN client's internal endpoint
+    pub fn lease_of(
+        &self,
+        internal_ip: [u8; 4],
+        internal_port: u16,
+        proto: Protocol,
+    ) -> Option<PortForward> {
+        self.forwards
+            .find_internal_lease(&internal_ip, internal_port, proto)
+            .copied()
+    }
+
+    /// Leased port forward number `index` with its remaining lifetime in
+    /// seconds
+    pub fn lease_at(&self, index: usize) -> Option<(PortForward, u32)> {
+        let now = self.clock.now_ms();
+        self.forwards.leases().nth(index).map(|rule| {
+            let remaining = rule
+                .lease
+                .map_or(0, |l| (l.expires.saturating_sub(now) / 1000) as u32);
+            (*rule, remaining)
+        })
+    }
+
+    /// Seconds since the external address was configured (NAT-PMP/PCP epoch)
+    pub fn epoch(&self) -> u32 {
+        (self.clock.now_ms().saturating_sub(self.epoch_start) / 1000) as u32
+    }
+
+    /// Override the mapping quota of one internal host, `None` restores
+    /// the default from `NatConfig::quota`
+    pub fn set_host_quota(&mut self, ip: [u8; 4], quota: Option<Quota>) -> Result<(), NatError> {
+        self.quotas.set_override(ip, quota)
+    }
+
+    /// Mapping counts and quota hits of an internal host
+    pub fn host_usage(&self, ip: &[u8; 4]) -> Option<&HostUsage> {
+        self.quotas.usage(ip)
+    }
+
+    /// Current NAT configuration
+    pub fn config(&self) -> &NatConfig<T> {
+        &self.config
+    }
+
+    /// Set NAT configuration (called from net stack)
+    pub fn set_config(&mut self, config: NatConfig<T>) {
+        if config.external_ip != self.config.external_ip {
+            self.epoch_start = self.clock.now_ms();
+        }
+        self.config = config;
+        self.peak_usage = self.peak_usage.max(self.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::testing::{
+        self, udp, TestTable, EXTERNAL_IP, HOST_IP, LAN, REMOTE_IP, WAN, WRAP_32,
+    };
+
+    const CAPACITY: usize = 16;
+
+    fn table(clock: &MockClock) -> TestTable<'_, CAPACITY> {
+        let config = NatConfig {
+            quota: Quota::from_limits(0, 0, 0, 0),
+            ..testing::config()
+        };
+        NatTable::new(config, clock)
+    }
+
+    /// Send from an internal host, returning the external port
+    fn outbound(table: &mut TestTable<CAPACITY>, src_port: u16) -> u16 {
+        let pkt = udp(HOST_IP, src_port, REMOTE_IP, 53, b"");
+        let mut ctx = PacketContext::parse(&pkt, LAN).unwrap();
+        table.translate_outbound(&mut ctx).unwrap();
+        ctx.src_port
+    }
+
+    fn reply(table: &mut TestTable<CAPACITY>, external_port: u16) -> Result<(), NatError> {
+        let pkt = udp(REMOTE_IP, 53, EXTERNAL_IP, external_port, b"");
+        let mut ctx = PacketContext::parse(&pkt, WAN).unwrap();
+        table.translate_inbound(&mut ctx)
+    }
+
+    #[test]
+    fn udp_mapping_expires_across_32bit_wrap() {
+        let clock = MockClock::new(WRAP_32 - 1_000);
+        let mut table = table(&clock);
+        let timeout = table.config().timeouts.udp_ms as u64;
+
+        let port = outbound(&mut table, 40000);
+        clock.advance(timeout);
+        assert_eq!(reply(&mut table, port), Ok(()));
+
+        clock.advance(timeout + 1);
+        assert_eq!(table.housekeeping().expired, 1);
+        assert_eq!(table.len(), 0);
+        assert_eq!(reply(&mut table, port), Err(NatError::NoMapping));
+    }
+
+    #[test]
+    fn full_table_evicts_least_recently_used() {
+        let clock = MockClock::new(0);
+        let mut table = table(&clock);
+
+        for i in 0..CAPACITY as u16 {
+            outbound(&mut table, 1000 + i);
+            clock.advance(1);
+        }
+        // Refresh the oldest session so the second one is the idlest
+        outbound(&mut table, 1000);
+
+        outbound(&mut table, 5000);
+        let end = table.pop_session_end().unwrap();
+        assert_eq!(end.reason, SessionEndReason::Evicted);
+        assert_eq!(end.entry.internal_port, 1001);
+        assert_eq!(table.stats().evicted, 1);
+        assert_eq!(table.len(), CAPACITY);
+    }
+
+    #[test]
+    fn lease_lifetime_follows_clock() {
+        let clock = MockClock::new(WRAP_32 - 500);
+        let mut table = table(&clock);
+
+        table
+            .map_lease(HOST_IP, 6000, Protocol::Udp, 6000, 2, [0; 12], true)
+            .unwrap();
+        clock.advance(1_000);
+        assert!(table.lease_at(0).is_some());
+
+        clock.advance(1_001);
+        table.housekeeping();
+        assert!(table.lease_at(0).is_none());
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+//! Tables and synthetic IPv4 packets for the tests, and the interface
+//! handles the host tools hand to the table.
+
+extern crate std;
+
+use crate::checksum::ip_checksum;
+use crate::clock::MockClock;
+use crate::{NatConfig, NatError, NatTable, NetIf, PacketContext};
+use std::vec;
+use std::vec::Vec;
+
+/// Interface handles, compared but never dereferenced
+pub const LAN: *mut NetIf = 0x1000 as *mut NetIf;
+pub const WAN: *mut NetIf = 0x2000 as *mut NetIf;
+
+pub const HOST_IP: [u8; 4] = [192, 168, 4, 2];
+pub const EXTERNAL_IP: [u8; 4] = [203, 0, 113, 1];
+pub const REMOTE_IP: [u8; 4] = [198, 51, 100, 7];
+pub const ROUTER_IP: [u8; 4] = [198, 51, 100, 254];
+
+/// Uptime (ms) at which a 32-bit millisecond clock wraps
+pub const WRAP_32: u64 = 1 << 32;
+
+/// Table reading a test clock
+pub type TestTable<'a, const N: usize> = NatTable<&'a MockClock, N>;
+
+/// NAT of 192.168.4.0/24 behind `EXTERNAL_IP`, between `LAN` and `WAN`
+pub fn config() -> NatConfig {
+    NatConfig {
+        internal_network: [192, 168, 4, 0],
+        internal_netmask: [255, 255, 255, 0],
+        external_ip: EXTERNAL_IP,
+        internal_iface: LAN,
+        external_iface: WAN,
+        ..NatConfig::default()
+    }
+}
+
+/// Table with the default `config()`
+pub fn table<const N: usize>(clock: &MockClock) -> TestTable<'_, N> {
+    NatTable::new(config(), clock)
+}
+
+/// IPv4 header with a valid checksum followed by `l4`
+pub fn ipv4(src: [u8; 4], dst: [u8; 4], proto: u8, l4: &[u8]) -> Vec<u8> {
+    let mut pkt = vec![0u8; 20];
+    pkt[0] = 0x45;
+    pkt[2..4].copy_from_slice(&((20 + l4.len()) as u16).to_be_bytes());
+    pkt[4..6].copy_from_slice(&0x1234u16.to_be_bytes());
+    pkt[8] = 64;
+    pkt[9] = proto;
+    pkt[12..16].copy_from_slice(&src);
+    pkt[16..20].copy_from_slice(&dst);
+    let csum = ip_checksum(&pkt);
+    pkt[10..12].copy_from_slice(&csum.to_be_bytes());
+    pkt.extend_from_slice(l4);
+    pkt
+}
+
+/// Checksum over the pseudo header and `l4`
+pub fn l4_checksum(src: [u8; 4], dst: [u8; 4], proto: u8, l4: &[u8]) -> u16 {
+    let mut data = Vec::new();
+    data.extend_from_slice(&src);
+    data.extend_from_slice(&dst);
+    data.extend_from_slice(&[0, proto]);
+    data.extend_from_slice(&(l4.len() as u16).to_be_bytes());
+    data.extend_from_slice(l4);
+    ip_checksum(&data)
+}
+
+/// UDP datagram with a valid checksum
+pub fn udp(src: [u8; 4], src_port: u16, dst: [u8; 4], dst_port: u16, payload: &[u8]) -> Vec<u8> {
+    let mut l4 = Vec::new();
+    l4.extend_from_slice(&src_port.to_be_bytes());
+    l4.extend_from_slice(&dst_port.to_be_bytes());
+    l4.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+    l4.extend_from_slice(&[0, 0]);
+    l4.extend_from_slice(payload);
+    let csum = l4_checksum(src, dst, 17, &l4);
+    l4[6..8].copy_from_slice(&csum.to_be_bytes());
+    ipv4(src, dst, 17, &l4)
+}
+
+/// TCP segment without options
+pub fn tcp(src: [u8; 4], src_port: u16, dst: [u8; 4], dst_port: u16, flags: u8) -> Vec<u8> {
+    let mut l4 = vec![0u8; 20];
+    l4[0..2].copy_from_slice(&src_port.to_be_bytes());
+    l4[2..4].copy_from_slice(&dst_port.to_be_bytes());
+    l4[4..8].copy_from_slice(&1000u32.to_be_bytes());
+    l4[12] = 5 << 4;
+    l4[13] = flags;
+    l4[14..16].copy_from_slice(&8192u16.to_be_bytes());
+    let csum = l4_checksum(src, dst, 6, &l4);
+    l4[16..18].copy_from_slice(&csum.to_be_bytes());
+    ipv4(src, dst, 6, &l4)
+}
+
+/// ICMP message with `rest` after the checksum
+pub fn icmp(src: [u8; 4], dst: [u8; 4], icmp_type: u8, rest: [u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut l4 = vec![icmp_type, 0, 0, 0];
+    l4.extend_from_slice(&rest);
+    l4.extend_from_slice(body);
+    let csum = ip_checksum(&l4);
+    l4[2..4].copy_from_slice(&csum.to_be_bytes());
+    ipv4(src, dst, 1, &l4)
+}
+
+/// ICMP echo request or reply with identifier `id`
+pub fn echo(src: [u8; 4], dst: [u8; 4], icmp_type: u8, id: u16) -> Vec<u8> {
+    let [hi, lo] = id.to_be_bytes();
+    icmp(src, dst, icmp_type, [hi, lo, 0, 1], b"ping")
+}
+
+pub fn src_ip(pkt: &[u8]) -> [u8; 4] {
+    pkt[12..16].try_into().unwrap()
+}
+
+pub fn dst_ip(pkt: &[u8]) -> [u8; 4] {
+    pkt[16..20].try_into().unwrap()
+}
+
+pub fn word(pkt: &[u8], off: usize) -> u16 {
+    u16::from_be_bytes([pkt[off], pkt[off + 1]])
+}
+
+/// Check the IP header checksum and the L4 checksum of `pkt`
+pub fn assert_checksums(pkt: &[u8]) {
+    let ihl = ((pkt[0] & 0x0F) as usize) * 4;
+    assert_eq!(ip_checksum(&pkt[..ihl]), 0, "IP header checksum");
+
+    let l4 = &pkt[ihl..];
+    match pkt[9] {
+        1 => assert_eq!(ip_checksum(l4), 0, "ICMP checksum"),
+        proto => assert_eq!(
+            l4_checksum(src_ip(pkt), dst_ip(pkt), proto, l4),
+            0,
+            "L4 checksum"
+        ),
+    }
+}
+
+/// Bytes `start..end` of the datagram payload as an IPv4 fragment
+pub fn fragment(pkt: &[u8], start: usize, end: usize, more: bool) -> Vec<u8> {
+    let payload = &pkt[20..];
+    let mut frag = pkt[..20].to_vec();
+    frag[2..4].copy_from_slice(&((20 + end - start) as u16).to_be_bytes());
+    let flags = if more { 0x2000 } else { 0 };
+    frag[6..8].copy_from_slice(&(flags | (start / 8) as u16).to_be_bytes());
+    frag[10..12].copy_from_slice(&[0, 0]);
+    let csum = ip_checksum(&frag);
+    frag[10..12].copy_from_slice(&csum.to_be_bytes());
+    frag.extend_from_slice(&payload[start..end]);
+    frag
+}
+
+/// Datagram rebuilt from its translated first and later fragment
+pub fn reassemble(first: &[u8], later: &[u8]) -> Vec<u8> {
+    let mut pkt = first.to_vec();
+    pkt.extend_from_slice(&later[20..]);
+    let len = pkt.len() as u16;
+    pkt[2..4].copy_from_slice(&len.to_be_bytes());
+    pkt[6..8].copy_from_slice(&[0, 0]);
+    pkt[10..12].copy_from_slice(&[0, 0]);
+    let csum = ip_checksum(&pkt[..20]);
+    pkt[10..12].copy_from_slice(&csum.to_be_bytes());
+    pkt
+}
+
+/// TCP segment carrying a PPTP control message with its two call IDs
+pub fn pptp(
+    src: [u8; 4],
+    src_port: u16,
+    dst: [u8; 4],
+    dst_port: u16,
+    kind: u16,
+    call_id: u16,
+    peer_call_id: u16,
+) -> Vec<u8> {
+    let mut msg = vec![0u8; 32];
+    msg[0..2].copy_from_slice(&32u16.to_be_bytes());
+    msg[2..4].copy_from_slice(&1u16.to_be_bytes());
+    msg[4..8].copy_from_slice(&0x1A2B3C4Du32.to_be_bytes());
+    msg[8..10].copy_from_slice(&kind.to_be_bytes());
+    msg[12..14].copy_from_slice(&call_id.to_be_bytes());
+    msg[14..16].copy_from_slice(&peer_call_id.to_be_bytes());
+
+    let mut l4 = vec![0u8; 20];
+    l4[0..2].copy_from_slice(&src_port.to_be_bytes());
+    l4[2..4].copy_from_slice(&dst_port.to_be_bytes());
+    l4[12] = 5 << 4;
+    l4[13] = 0x18;
+    l4.extend_from_slice(&msg);
+    let csum = l4_checksum(src, dst, 6, &l4);
+    l4[16..18].copy_from_slice(&csum.to_be_bytes());
+    ipv4(src, dst, 6, &l4)
+}
+
+/// PPTP enhanced GRE packet for `call_id`
+pub fn gre(src: [u8; 4], dst: [u8; 4], call_id: u16) -> Vec<u8> {
+    let mut l4 = vec![0x30, 0x01, 0x88, 0x0B, 0x00, 0x04];
+    l4.extend_from_slice(&call_id.to_be_bytes());
+    l4.extend_from_slice(&[0, 0, 0, 1]);
+    l4.extend_from_slice(b"ppp!");
+    ipv4(src, dst, 47, &l4)
+}
+
+/// ESP packet of the SA `spi`
+pub fn esp(src: [u8; 4], dst: [u8; 4], spi: u32) -> Vec<u8> {
+    let mut l4 = spi.to_be_bytes().to_vec();
+    l4.extend_from_slice(&[0, 0, 0, 1]);
+    l4.extend_from_slice(&[0x5A; 16]);
+    ipv4(src, dst, 50, &l4)
+}
+
+/// Translate a LAN packet in place, it has to leave through the WAN
+pub fn outbound<const N: usize>(table: &mut TestTable<N>, pkt: &mut [u8]) -> Result<(), NatError> {
+    let mut ctx = PacketContext::parse(pkt, LAN).unwrap();
+    table.translate_outbound(&mut ctx)?;
+    assert_eq!(ctx.iface, WAN);
+    ctx.apply(pkt);
+    Ok(())
+}
+
+/// Translate a WAN packet in place, it has to go out to the LAN
+pub fn inbound<const N: usize>(table: &mut TestTable<N>, pkt: &mut [u8]) -> Result<(), NatError> {
+    let mut ctx = PacketContext::parse(pkt, WAN).unwrap();
+    table.translate_inbound(&mut ctx)?;
+    assert_eq!(ctx.iface, LAN);
+    ctx.apply(pkt);
+    Ok(())
+}
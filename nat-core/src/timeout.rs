@@ -0,0 +1,129 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+use super::entry::{NatEntry, Protocol, TcpState};
+use super::table::NatError;
+use heapless::Vec;
+
+/// Per-port timeout overrides held at once, unless configured otherwise
+pub const MAX_TIMEOUT_OVERRIDES: usize = 8;
+
+pub const DNS_PORT: u16 = 53;
+pub const IKE_PORT: u16 = 500;
+pub const IPSEC_NAT_T_PORT: u16 = 4500;
+
+/// Milliseconds in `value` seconds, saturating
+pub const fn seconds(value: u32) -> u32 {
+    value.saturating_mul(1000)
+}
+
+/// Idle timeout of sessions to one remote (destination) port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortTimeout {
+    pub protocol: Protocol,
+    pub port: u16,
+    pub timeout_ms: u32,
+}
+
+/// Idle timeouts of NAT sessions
+///
+/// Port overrides replace the UDP timeout, and the established timeout
+/// for TCP; other TCP states keep their own. Up to `T` overrides are
+/// held at once.
+#[derive(Debug, Clone)]
+pub struct TimeoutPolicy<const T: usize = MAX_TIMEOUT_OVERRIDES> {
+    pub tcp_established_ms: u32,
+    /// Half-open and half-closed connections
+    pub tcp_transitory_ms: u32,
+    pub tcp_time_wait_ms: u32,
+    /// After a RST
+    pub tcp_closed_ms: u32,
+    pub udp_ms: u32,
+    pub icmp_ms: u32,
+    /// GRE, ESP and address-only mappings of other IP protocols
+    pub other_ms: u32,
+    pub port_overrides: Vec<PortTimeout, T>,
+}
+
+impl<const T: usize> Default for TimeoutPolicy<T> {
+    fn default() -> Self {
+        let mut policy = Self {
+            // RFC 5382 REQ-5: at least 2 h 4 min
+            tcp_established_ms: seconds(7440),
+            tcp_transitory_ms: seconds(120),
+            tcp_time_wait_ms: seconds(60),
+            tcp_closed_ms: seconds(10),
+            // RFC 4787 REQ-5: at least 2 min, 5 recommended
+            udp_ms: seconds(300),
+            // RFC 5508 REQ-1: at least 60 s
+            icmp_ms: seconds(60),
+            // Linux nf_conntrack_generic_timeout
+            other_ms: seconds(600),
+            port_overrides: Vec::new(),
+        };
+
+        let _ = policy.set_port_override(Protocol::Udp, DNS_PORT, Some(seconds(10)));
+        let _ = policy.set_port_override(Protocol::Udp, IPSEC_NAT_T_PORT, Some(seconds(3600)));
+
+        policy
+    }
+}
+
+impl<const T: usize> TimeoutPolicy<T> {
+    /// Set the timeout of sessions to `port`, `None` removes the override
+    pub fn set_port_override(
+        &mut self,
+        protocol: Protocol,
+        port: u16,
+        timeout_ms: Option<u32>,
+    ) -> Result<(), NatError> {
+        let existing = self
+            .port_overrides
+            .iter()
+            .position(|o| o.protocol == protocol && o.port == port);
+
+        match (existing, timeout_ms) {
+            (Some(idx), Some(timeout_ms)) => self.port_overrides[idx].timeout_ms = timeout_ms,
+            (Some(idx), None) => {
+                self.port_overrides.swap_remove(idx);
+            }
+            (None, Some(timeout_ms)) => self
+                .port_overrides
+                .push(PortTimeout {
+                    protocol,
+                    port,
+                    timeout_ms,
+                })
+                .map_err(|_| NatError::TableFull)?,
+            (None, None) => {}
+        }
+        Ok(())
+    }
+
+    fn port_override(&self, protocol: Protocol, port: u16) -> Option<u32> {
+        self.port_overrides
+            .iter()
+            .find(|o| o.protocol == protocol && o.port == port)
+            .map(|o| o.timeout_ms)
+    }
+
+    /// Idle timeout of a session in its current state
+    pub fn timeout_ms(&self, entry: &NatEntry) -> u32 {
+        match entry.protocol {
+            Protocol::Tcp => match entry.tcp_state {
+                TcpState::Established => self
+                    .port_override(Protocol::Tcp, entry.remote_port)
+                    .unwrap_or(self.tcp_established_ms),
+                TcpState::SynSent | TcpState::FinWait { .. } => self.tcp_transitory_ms,
+                TcpState::TimeWait => self.tcp_time_wait_ms,
+                TcpState::Closed => self.tcp_closed_ms,
+            },
+            Protocol::Udp => self
+                .port_override(Protocol::Udp, entry.remote_port)
+                .unwrap_or(self.udp_ms),
+            Protocol::Icmp => self.icmp_ms,
+            Protocol::Gre | Protocol::Esp | Protocol::Other(_) => self.other_ms,
+        }
+    }
+}
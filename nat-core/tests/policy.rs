@@ -0,0 +1,563 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+//! Port forwards, leases, mapping and filtering behaviours and quotas.
+
+use nat_core::clock::MockClock;
+use nat_core::entry::Protocol;
+use nat_core::forward::PortForward;
+use nat_core::quota::Quota;
+use nat_core::table::{FilteringBehavior, MappingBehavior};
+use nat_core::testing::*;
+use nat_core::{NatConfig, NatError, NatTable, PacketContext};
+
+const CAPACITY: usize = 32;
+
+const OTHER_HOST_IP: [u8; 4] = [192, 168, 4, 3];
+const OTHER_REMOTE_IP: [u8; 4] = [198, 51, 100, 8];
+
+fn table(clock: &MockClock) -> TestTable<'_, CAPACITY> {
+    nat_core::testing::table(clock)
+}
+
+fn table_with(clock: &MockClock, config: NatConfig) -> TestTable<'_, CAPACITY> {
+    NatTable::new(config, clock)
+}
+
+/// Send a UDP datagram from `HOST_IP`, returning its external port
+fn send(table: &mut TestTable<CAPACITY>, src_port: u16, dst: [u8; 4], dst_port: u16) -> u16 {
+    let mut pkt = udp(HOST_IP, src_port, dst, dst_port, b"out");
+    outbound(table, &mut pkt).unwrap();
+    word(&pkt, 20)
+}
+
+/// Deliver a UDP datagram from a remote endpoint to `external_port`
+fn receive(
+    table: &mut TestTable<CAPACITY>,
+    src: [u8; 4],
+    src_port: u16,
+    external_port: u16,
+) -> Result<Vec<u8>, NatError> {
+    let mut pkt = udp(src, src_port, EXTERNAL_IP, external_port, b"in");
+    inbound(table, &mut pkt)?;
+    Ok(pkt)
+}
+
+/// Translate a LAN packet to the external address, returning whether it
+/// was looped back to the LAN
+fn hairpin(table: &mut TestTable<CAPACITY>, pkt: &mut [u8]) -> Result<bool, NatError> {
+    let mut ctx = PacketContext::parse(pkt, LAN).unwrap();
+    table.translate_outbound(&mut ctx)?;
+    ctx.apply(pkt);
+    Ok(ctx.needs_update)
+}
+
+#[test]
+fn port_forward_range_maps_one_to_one() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+    table
+        .add_port_forward(PortForward {
+            protocol: Protocol::Tcp,
+            external_start: 8080,
+            external_end: 8081,
+            internal_ip: HOST_IP,
+            internal_port: 80,
+            lease: None,
+        })
+        .unwrap();
+
+    let mut syn = tcp(REMOTE_IP, 50000, EXTERNAL_IP, 8081, 0x02);
+    inbound(&mut table, &mut syn).unwrap();
+    assert_eq!(dst_ip(&syn), HOST_IP);
+    assert_eq!(word(&syn, 22), 81);
+    assert_checksums(&syn);
+
+    let mut syn_ack = tcp(HOST_IP, 81, REMOTE_IP, 50000, 0x12);
+    outbound(&mut table, &mut syn_ack).unwrap();
+    assert_eq!(src_ip(&syn_ack), EXTERNAL_IP);
+    assert_eq!(word(&syn_ack, 20), 8081);
+    assert_checksums(&syn_ack);
+
+    // Other protocols and ports are not forwarded
+    assert!(receive(&mut table, REMOTE_IP, 50000, 8081).is_err());
+    let mut other = tcp(REMOTE_IP, 50000, EXTERNAL_IP, 8082, 0x02);
+    assert!(inbound(&mut table, &mut other).is_err());
+}
+
+#[test]
+fn overlapping_port_forwards_conflict() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+    let rule = PortForward {
+        protocol: Protocol::Udp,
+        external_start: 5000,
+        external_end: 5009,
+        internal_ip: HOST_IP,
+        internal_port: 5000,
+        lease: None,
+    };
+    table.add_port_forward(rule).unwrap();
+
+    let overlap = PortForward {
+        external_start: 5009,
+        external_end: 5010,
+        internal_ip: OTHER_HOST_IP,
+        ..rule
+    };
+    assert_eq!(table.add_port_forward(overlap), Err(NatError::RuleConflict));
+
+    assert!(table.remove_port_forward(Protocol::Udp, 5000));
+    assert_eq!(table.add_port_forward(overlap), Ok(()));
+}
+
+#[test]
+fn rule_and_override_capacity_follow_the_table() {
+    let clock = MockClock::new(0);
+    let mut table: NatTable<&MockClock, CAPACITY, 1, 1> = NatTable::new(config(), &clock);
+    let rule = PortForward {
+        protocol: Protocol::Tcp,
+        external_start: 8080,
+        external_end: 8080,
+        internal_ip: HOST_IP,
+        internal_port: 80,
+        lease: None,
+    };
+    table.add_port_forward(rule).unwrap();
+    let second = PortForward {
+        external_start: 8081,
+        external_end: 8081,
+        ..rule
+    };
+    assert_eq!(table.add_port_forward(second), Err(NatError::TableFull));
+
+    let quota = Some(Quota::from_limits(4, 0, 0, 0));
+    table.set_host_quota(HOST_IP, quota).unwrap();
+    assert_eq!(
+        table.set_host_quota(OTHER_HOST_IP, quota),
+        Err(NatError::TableFull)
+    );
+
+    // The default configuration already overrides DNS and NAT-T
+    let mut config = NatConfig::<4>::default();
+    assert_eq!(config.timeouts.port_overrides.len(), 2);
+    config
+        .timeouts
+        .set_port_override(Protocol::Tcp, 22, Some(1000))
+        .unwrap();
+    config
+        .timeouts
+        .set_port_override(Protocol::Tcp, 23, Some(1000))
+        .unwrap();
+    assert_eq!(
+        config
+            .timeouts
+            .set_port_override(Protocol::Tcp, 24, Some(1000)),
+        Err(NatError::TableFull)
+    );
+}
+
+#[test]
+fn dmz_host_receives_unsolicited_traffic() {
+    let clock = MockClock::new(0);
+    let mut table = table_with(
+        &clock,
+        NatConfig {
+            dmz_host: Some(OTHER_HOST_IP),
+            ..config()
+        },
+    );
+    table
+        .add_port_forward(PortForward {
+            protocol: Protocol::Udp,
+            external_start: 7000,
+            external_end: 7000,
+            internal_ip: HOST_IP,
+            internal_port: 7000,
+            lease: None,
+        })
+        .unwrap();
+
+    let pkt = receive(&mut table, REMOTE_IP, 9999, 7001).unwrap();
+    assert_eq!(dst_ip(&pkt), OTHER_HOST_IP);
+    assert_eq!(word(&pkt, 22), 7001);
+    assert_checksums(&pkt);
+
+    let mut syn = tcp(REMOTE_IP, 50000, EXTERNAL_IP, 22, 0x02);
+    inbound(&mut table, &mut syn).unwrap();
+    assert_eq!(dst_ip(&syn), OTHER_HOST_IP);
+    assert_eq!(word(&syn, 22), 22);
+
+    // The DMZ host answers through the session it was given
+    let mut reply = udp(OTHER_HOST_IP, 7001, REMOTE_IP, 9999, b"out");
+    outbound(&mut table, &mut reply).unwrap();
+    assert_eq!(src_ip(&reply), EXTERNAL_IP);
+    assert_eq!(word(&reply, 20), 7001);
+
+    // Port forwards come first
+    let pkt = receive(&mut table, REMOTE_IP, 9999, 7000).unwrap();
+    assert_eq!(dst_ip(&pkt), HOST_IP);
+}
+
+#[test]
+fn dmz_host_answers_pings() {
+    let clock = MockClock::new(0);
+    let mut table = table_with(
+        &clock,
+        NatConfig {
+            dmz_host: Some(OTHER_HOST_IP),
+            ..config()
+        },
+    );
+
+    let mut ping = echo(REMOTE_IP, EXTERNAL_IP, 8, 7);
+    inbound(&mut table, &mut ping).unwrap();
+    assert_eq!(dst_ip(&ping), OTHER_HOST_IP);
+    assert_eq!(word(&ping, 24), 7);
+    assert_checksums(&ping);
+
+    let mut pong = echo(OTHER_HOST_IP, REMOTE_IP, 0, 7);
+    outbound(&mut table, &mut pong).unwrap();
+    assert_eq!(src_ip(&pong), EXTERNAL_IP);
+    assert_eq!(word(&pong, 24), 7);
+}
+
+#[test]
+fn dmz_host_receives_udp_from_service_ports() {
+    let clock = MockClock::new(0);
+    let mut table = table_with(
+        &clock,
+        NatConfig {
+            dmz_host: Some(OTHER_HOST_IP),
+            ..config()
+        },
+    );
+
+    // Only a socket the router has open makes the traffic local
+    for src_port in [53, 123] {
+        let pkt = receive(&mut table, REMOTE_IP, src_port, 40000).unwrap();
+        assert_eq!(dst_ip(&pkt), OTHER_HOST_IP);
+        assert_eq!(word(&pkt, 22), 40000);
+    }
+}
+
+#[test]
+fn dmz_leaves_router_traffic_to_the_local_stack() {
+    let clock = MockClock::new(0);
+    let mut config = NatConfig {
+        dmz_host: Some(OTHER_HOST_IP),
+        local_socket: Some(|proto, port| proto == Protocol::Udp && port == 40000),
+        ..config()
+    };
+    config.local_ports.push((Protocol::Tcp, 8443)).unwrap();
+    let mut table = table_with(&clock, config);
+
+    // Replies to the router's own pings
+    let mut pong = echo(REMOTE_IP, EXTERNAL_IP, 0, 7);
+    assert_eq!(inbound(&mut table, &mut pong), Err(NatError::NoMapping));
+
+    // DHCP client
+    assert!(receive(&mut table, [198, 51, 100, 1], 67, 68).is_err());
+
+    // A socket the router has open, such as its resolver's
+    assert!(receive(&mut table, REMOTE_IP, 53, 40000).is_err());
+
+    // Ports of the router's services
+    let mut syn = tcp(REMOTE_IP, 50000, EXTERNAL_IP, 8443, 0x02);
+    assert!(inbound(&mut table, &mut syn).is_err());
+
+    assert!(table.is_empty());
+}
+
+#[test]
+fn endpoint_independent_mapping_shares_the_external_port() {
+    let clock = MockClock::new(0);
+    let mut eim = table_with(
+        &clock,
+        NatConfig {
+            mapping: MappingBehavior::EndpointIndependent,
+            ..config()
+        },
+    );
+    let first = send(&mut eim, 40000, REMOTE_IP, 53);
+    assert_eq!(send(&mut eim, 40000, REMOTE_IP, 54), first);
+    assert_eq!(send(&mut eim, 40000, OTHER_REMOTE_IP, 53), first);
+}
+
+#[test]
+fn address_dependent_mapping_splits_per_remote_address() {
+    let clock = MockClock::new(0);
+    let mut adm = table_with(
+        &clock,
+        NatConfig {
+            mapping: MappingBehavior::AddressDependent,
+            ..config()
+        },
+    );
+    let first = send(&mut adm, 40000, REMOTE_IP, 53);
+    assert_eq!(send(&mut adm, 40000, REMOTE_IP, 54), first);
+    assert_ne!(send(&mut adm, 40000, OTHER_REMOTE_IP, 53), first);
+}
+
+#[test]
+fn address_and_port_dependent_mapping_splits_per_session() {
+    let clock = MockClock::new(0);
+    let mut apdm = table(&clock);
+    let first = send(&mut apdm, 40000, REMOTE_IP, 53);
+    assert_eq!(send(&mut apdm, 40000, REMOTE_IP, 53), first);
+    assert_ne!(send(&mut apdm, 40000, REMOTE_IP, 54), first);
+}
+
+#[test]
+fn filtering_limits_who_may_use_a_mapping() {
+    let clock = MockClock::new(0);
+    let eim = |filtering| NatConfig {
+        mapping: MappingBehavior::EndpointIndependent,
+        filtering,
+        ..config()
+    };
+
+    let mut eif = table_with(&clock, eim(FilteringBehavior::EndpointIndependent));
+    let port = send(&mut eif, 40000, REMOTE_IP, 53);
+    let pkt = receive(&mut eif, OTHER_REMOTE_IP, 9999, port).unwrap();
+    assert_eq!(dst_ip(&pkt), HOST_IP);
+    assert_eq!(word(&pkt, 22), 40000);
+
+    let mut adf = table_with(&clock, eim(FilteringBehavior::AddressDependent));
+    let port = send(&mut adf, 40000, REMOTE_IP, 53);
+    assert!(receive(&mut adf, REMOTE_IP, 9999, port).is_ok());
+    assert!(receive(&mut adf, OTHER_REMOTE_IP, 53, port).is_err());
+
+    let mut apdf = table_with(&clock, eim(FilteringBehavior::AddressAndPortDependent));
+    let port = send(&mut apdf, 40000, REMOTE_IP, 53);
+    assert!(receive(&mut apdf, REMOTE_IP, 53, port).is_ok());
+    assert!(receive(&mut apdf, REMOTE_IP, 9999, port).is_err());
+    assert!(receive(&mut apdf, OTHER_REMOTE_IP, 53, port).is_err());
+}
+
+#[test]
+fn host_quota_refuses_new_sessions() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+    table
+        .set_host_quota(HOST_IP, Some(Quota::from_limits(0, 0, 2, 0)))
+        .unwrap();
+
+    send(&mut table, 40000, REMOTE_IP, 53);
+    send(&mut table, 40001, REMOTE_IP, 53);
+    let mut third = udp(HOST_IP, 40002, REMOTE_IP, 53, b"out");
+    assert_eq!(
+        outbound(&mut table, &mut third),
+        Err(NatError::QuotaExceeded)
+    );
+
+    // Other protocols and other hosts are not limited by the override
+    let mut ping = echo(HOST_IP, REMOTE_IP, 8, 7);
+    outbound(&mut table, &mut ping).unwrap();
+    let mut other = udp(OTHER_HOST_IP, 40002, REMOTE_IP, 53, b"out");
+    outbound(&mut table, &mut other).unwrap();
+
+    let usage = table.host_usage(&HOST_IP).unwrap();
+    assert_eq!(usage.mappings(Protocol::Udp), 2);
+    assert_eq!(usage.total(), 3);
+    assert_eq!(usage.hits, 1);
+
+    // Restoring the default quota lifts the limit
+    table.set_host_quota(HOST_IP, None).unwrap();
+    outbound(&mut table, &mut third).unwrap();
+}
+
+#[test]
+fn lease_forwards_until_it_expires() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+    let nonce = [7; 12];
+
+    let port = table
+        .map_lease(HOST_IP, 6000, Protocol::Udp, 16000, 60, nonce, false)
+        .unwrap();
+    assert_eq!(port, 16000);
+    let pkt = receive(&mut table, REMOTE_IP, 53, port).unwrap();
+    assert_eq!(dst_ip(&pkt), HOST_IP);
+    assert_eq!(word(&pkt, 22), 6000);
+
+    let (rule, remaining) = table.lease_at(0).unwrap();
+    assert_eq!(rule.internal_port, 6000);
+    assert_eq!(remaining, 60);
+    assert_eq!(table.lease_of(HOST_IP, 6000, Protocol::Udp), Some(rule));
+    assert_eq!(table.lease_of(HOST_IP, 6000, Protocol::Tcp), None);
+
+    clock.advance(60_000);
+    table.housekeeping();
+    assert!(table.lease_at(0).is_none());
+    assert!(receive(&mut table, OTHER_REMOTE_IP, 53, port).is_err());
+}
+
+#[test]
+fn lease_belongs_to_its_requester() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+    let nonce = [7; 12];
+
+    let port = table
+        .map_lease(HOST_IP, 6000, Protocol::Udp, 16000, 60, nonce, false)
+        .unwrap();
+
+    // Renewal needs the same nonce
+    assert_eq!(
+        table.map_lease(HOST_IP, 6000, Protocol::Udp, 16000, 120, [8; 12], false),
+        Err(NatError::NotAuthorized)
+    );
+    assert_eq!(
+        table.map_lease(HOST_IP, 6000, Protocol::Udp, 16000, 120, nonce, false),
+        Ok(port)
+    );
+
+    // The port is taken for other hosts
+    assert_eq!(
+        table.map_lease(OTHER_HOST_IP, 6000, Protocol::Udp, port, 60, nonce, true),
+        Err(NatError::RuleConflict)
+    );
+    assert_eq!(
+        table.unmap_lease_external(OTHER_HOST_IP, port, Protocol::Udp),
+        Err(NatError::NotAuthorized)
+    );
+
+    assert_eq!(
+        table.unmap_lease(HOST_IP, Some(6000), Some(Protocol::Udp), Some([8; 12])),
+        Err(NatError::NotAuthorized)
+    );
+    assert_eq!(
+        table.unmap_lease(HOST_IP, Some(6000), Some(Protocol::Udp), Some(nonce)),
+        Ok(1)
+    );
+    assert!(table.lease_at(0).is_none());
+    assert!(receive(&mut table, REMOTE_IP, 53, port).is_err());
+}
+
+#[test]
+fn hairpin_reaches_port_forward() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+    table
+        .add_port_forward(PortForward {
+            protocol: Protocol::Tcp,
+            external_start: 8080,
+            external_end: 8080,
+            internal_ip: HOST_IP,
+            internal_port: 80,
+            lease: None,
+        })
+        .unwrap();
+
+    let mut syn = tcp(OTHER_HOST_IP, 50000, EXTERNAL_IP, 8080, 0x02);
+    assert_eq!(hairpin(&mut table, &mut syn), Ok(true));
+    assert_eq!(src_ip(&syn), EXTERNAL_IP);
+    assert_eq!(dst_ip(&syn), HOST_IP);
+    assert_eq!(word(&syn, 22), 80);
+    assert_checksums(&syn);
+
+    // The server answers the client's external endpoint
+    let client_port = word(&syn, 20);
+    let mut syn_ack = tcp(HOST_IP, 80, EXTERNAL_IP, client_port, 0x12);
+    assert_eq!(hairpin(&mut table, &mut syn_ack), Ok(true));
+    assert_eq!(src_ip(&syn_ack), EXTERNAL_IP);
+    assert_eq!(word(&syn_ack, 20), 8080);
+    assert_eq!(dst_ip(&syn_ack), OTHER_HOST_IP);
+    assert_eq!(word(&syn_ack, 22), 50000);
+    assert_checksums(&syn_ack);
+}
+
+#[test]
+fn hairpin_miss_reaches_the_router_untouched() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+
+    let mut pkt = udp(HOST_IP, 40000, EXTERNAL_IP, 9000, b"local");
+    let before = pkt.clone();
+    assert_eq!(hairpin(&mut table, &mut pkt), Ok(false));
+    assert_eq!(pkt, before);
+    assert!(table.is_empty());
+    assert!(table
+        .host_usage(&HOST_IP)
+        .is_none_or(|usage| usage.total() == 0));
+}
+
+#[test]
+fn hairpin_reuses_mappings_both_ways() {
+    let clock = MockClock::new(0);
+    let mut table = table_with(
+        &clock,
+        NatConfig {
+            mapping: MappingBehavior::EndpointIndependent,
+            filtering: FilteringBehavior::EndpointIndependent,
+            ..config()
+        },
+    );
+
+    // The server's mapping, as learned by a peer from a rendezvous server
+    let server_port = send(&mut table, 40000, REMOTE_IP, 3478);
+    let client_port = {
+        let mut pkt = udp(OTHER_HOST_IP, 41000, REMOTE_IP, 3478, b"out");
+        outbound(&mut table, &mut pkt).unwrap();
+        word(&pkt, 20)
+    };
+
+    let mut request = udp(OTHER_HOST_IP, 41000, EXTERNAL_IP, server_port, b"hi");
+    assert_eq!(hairpin(&mut table, &mut request), Ok(true));
+    assert_eq!(src_ip(&request), EXTERNAL_IP);
+    assert_eq!(word(&request, 20), client_port);
+    assert_eq!(dst_ip(&request), HOST_IP);
+    assert_eq!(word(&request, 22), 40000);
+    assert_checksums(&request);
+    let sessions = table.len();
+
+    let mut response = udp(HOST_IP, 40000, EXTERNAL_IP, client_port, b"hello");
+    assert_eq!(hairpin(&mut table, &mut response), Ok(true));
+    assert_eq!(src_ip(&response), EXTERNAL_IP);
+    assert_eq!(word(&response, 20), server_port);
+    assert_eq!(dst_ip(&response), OTHER_HOST_IP);
+    assert_eq!(word(&response, 22), 41000);
+    assert_checksums(&response);
+
+    let mut again = udp(OTHER_HOST_IP, 41000, EXTERNAL_IP, server_port, b"again");
+    assert_eq!(hairpin(&mut table, &mut again), Ok(true));
+    assert_eq!(word(&again, 20), client_port);
+    assert_eq!(table.len(), sessions);
+}
+
+#[test]
+fn lease_delete_covers_all_protocols() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+    let nonce = [7; 12];
+
+    for proto in [Protocol::Udp, Protocol::Tcp] {
+        table
+            .map_lease(HOST_IP, 6000, proto, 16000, 60, nonce, false)
+            .unwrap();
+    }
+    table
+        .map_lease(
+            OTHER_HOST_IP,
+            6000,
+            Protocol::Udp,
+            16001,
+            60,
+            [9; 12],
+            false,
+        )
+        .unwrap();
+
+    // Another client's nonce removes nothing
+    assert_eq!(
+        table.unmap_lease(HOST_IP, None, None, Some([9; 12])),
+        Err(NatError::NotAuthorized)
+    );
+    assert_eq!(table.unmap_lease(HOST_IP, None, None, Some(nonce)), Ok(2));
+
+    let (rule, _) = table.lease_at(0).unwrap();
+    assert_eq!(rule.internal_ip, OTHER_HOST_IP);
+    assert!(table.lease_at(1).is_none());
+}
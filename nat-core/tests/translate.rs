@@ -0,0 +1,473 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+//! Synthetic IPv4 packets run through parse, translate and apply.
+
+use nat_core::checksum::ip_checksum;
+use nat_core::clock::MockClock;
+use nat_core::testing::*;
+use nat_core::{NatError, PacketContext, SessionEndReason};
+
+const CAPACITY: usize = 32;
+
+fn table(clock: &MockClock) -> TestTable<'_, CAPACITY> {
+    nat_core::testing::table(clock)
+}
+
+#[test]
+fn udp_round_trip() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+
+    let mut request = udp(HOST_IP, 40000, REMOTE_IP, 53, b"query");
+    outbound(&mut table, &mut request).unwrap();
+    assert_eq!(src_ip(&request), EXTERNAL_IP);
+    assert_eq!(dst_ip(&request), REMOTE_IP);
+    assert_eq!(word(&request, 22), 53);
+    assert_checksums(&request);
+
+    let external_port = word(&request, 20);
+    let mut response = udp(REMOTE_IP, 53, EXTERNAL_IP, external_port, b"answer");
+    inbound(&mut table, &mut response).unwrap();
+    assert_eq!(src_ip(&response), REMOTE_IP);
+    assert_eq!(dst_ip(&response), HOST_IP);
+    assert_eq!(word(&response, 20), 53);
+    assert_eq!(word(&response, 22), 40000);
+    assert_checksums(&response);
+}
+
+#[test]
+fn udp_without_checksum_stays_unchecked() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+
+    let mut request = udp(HOST_IP, 40001, REMOTE_IP, 123, b"ntp");
+    request[26..28].copy_from_slice(&[0, 0]);
+    outbound(&mut table, &mut request).unwrap();
+    assert_eq!(src_ip(&request), EXTERNAL_IP);
+    assert_eq!(word(&request, 26), 0);
+    assert_eq!(ip_checksum(&request[..20]), 0);
+}
+
+#[test]
+fn tcp_round_trip() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+
+    let mut syn = tcp(HOST_IP, 50000, REMOTE_IP, 443, 0x02);
+    outbound(&mut table, &mut syn).unwrap();
+    assert_eq!(src_ip(&syn), EXTERNAL_IP);
+    assert_eq!(word(&syn, 22), 443);
+    assert_eq!(syn[33], 0x02);
+    assert_checksums(&syn);
+
+    let external_port = word(&syn, 20);
+    let mut syn_ack = tcp(REMOTE_IP, 443, EXTERNAL_IP, external_port, 0x12);
+    inbound(&mut table, &mut syn_ack).unwrap();
+    assert_eq!(dst_ip(&syn_ack), HOST_IP);
+    assert_eq!(word(&syn_ack, 22), 50000);
+    assert_checksums(&syn_ack);
+}
+
+#[test]
+fn icmp_echo_round_trip() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+
+    let mut request = echo(HOST_IP, REMOTE_IP, 8, 0x0101);
+    outbound(&mut table, &mut request).unwrap();
+    assert_eq!(src_ip(&request), EXTERNAL_IP);
+    assert_checksums(&request);
+
+    let external_id = word(&request, 24);
+    let mut reply = echo(REMOTE_IP, EXTERNAL_IP, 0, external_id);
+    inbound(&mut table, &mut reply).unwrap();
+    assert_eq!(dst_ip(&reply), HOST_IP);
+    assert_eq!(word(&reply, 24), 0x0101);
+    assert_checksums(&reply);
+}
+
+#[test]
+fn icmp_error_rewrites_quoted_packet() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+
+    let mut request = udp(HOST_IP, 40002, REMOTE_IP, 33434, b"trace");
+    outbound(&mut table, &mut request).unwrap();
+
+    // Time exceeded from a router on the path, quoting the whole datagram
+    let mut error = icmp(ROUTER_IP, EXTERNAL_IP, 11, [0; 4], &request);
+    inbound(&mut table, &mut error).unwrap();
+    assert_eq!(src_ip(&error), ROUTER_IP);
+    assert_eq!(dst_ip(&error), HOST_IP);
+    assert_checksums(&error);
+
+    let quoted = &error[28..];
+    assert_eq!(src_ip(quoted), HOST_IP);
+    assert_eq!(dst_ip(quoted), REMOTE_IP);
+    assert_eq!(word(quoted, 20), 40002);
+    assert_eq!(word(quoted, 22), 33434);
+    assert_checksums(quoted);
+}
+
+#[test]
+fn unsolicited_inbound_is_dropped() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+
+    let mut pkt = udp(REMOTE_IP, 53, EXTERNAL_IP, 40000, b"x");
+    let before = pkt.clone();
+    assert!(inbound(&mut table, &mut pkt).is_err());
+    assert_eq!(pkt, before);
+}
+
+#[test]
+fn truncated_packets_are_rejected() {
+    let pkt = udp(HOST_IP, 40000, REMOTE_IP, 53, b"query");
+    assert!(PacketContext::parse(&pkt[..19], LAN).is_none());
+
+    let mut options = pkt.clone();
+    options[0] = 0x46;
+    assert!(PacketContext::parse(&options[..23], LAN).is_none());
+
+    let mut ipv6 = pkt;
+    ipv6[0] = 0x65;
+    assert!(PacketContext::parse(&ipv6, LAN).is_none());
+}
+
+#[test]
+fn fragments_follow_first_fragment() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+
+    let request = udp(HOST_IP, 40003, REMOTE_IP, 5060, &[0x55; 40]);
+    let mut first = fragment(&request, 0, 24, true);
+    let mut later = fragment(&request, 24, 48, false);
+
+    outbound(&mut table, &mut first).unwrap();
+    outbound(&mut table, &mut later).unwrap();
+    assert_eq!(src_ip(&later), EXTERNAL_IP);
+    assert_eq!(dst_ip(&later), REMOTE_IP);
+    assert_eq!(ip_checksum(&later[..20]), 0);
+    assert_eq!(later[20..], request[44..]);
+    assert_checksums(&reassemble(&first, &later));
+
+    let external_port = word(&first, 20);
+    let response = udp(REMOTE_IP, 5060, EXTERNAL_IP, external_port, &[0xAA; 40]);
+    let mut first = fragment(&response, 0, 24, true);
+    let mut later = fragment(&response, 24, 48, false);
+
+    inbound(&mut table, &mut first).unwrap();
+    inbound(&mut table, &mut later).unwrap();
+    assert_eq!(dst_ip(&later), HOST_IP);
+    assert_eq!(ip_checksum(&later[..20]), 0);
+    let datagram = reassemble(&first, &later);
+    assert_eq!(word(&datagram, 22), 40003);
+    assert_checksums(&datagram);
+
+    let stats = table.stats();
+    assert_eq!(stats.fragment_flows, 2);
+    assert_eq!(stats.fragments_unmatched, 0);
+}
+
+#[test]
+fn fragments_ahead_of_the_first_are_dropped() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+
+    let request = udp(HOST_IP, 40005, REMOTE_IP, 5060, &[0x55; 40]);
+    let mut first = fragment(&request, 0, 24, true);
+    let mut later = fragment(&request, 24, 48, false);
+
+    // Not held back, the sender has to retransmit it
+    let mut early = later.clone();
+    assert_eq!(
+        outbound(&mut table, &mut early),
+        Err(NatError::UnknownFragment)
+    );
+    assert_eq!(early, later);
+
+    outbound(&mut table, &mut first).unwrap();
+    outbound(&mut table, &mut later).unwrap();
+    assert_eq!(src_ip(&later), EXTERNAL_IP);
+
+    let external_port = word(&first, 20);
+    let response = udp(REMOTE_IP, 5060, EXTERNAL_IP, external_port, &[0xAA; 40]);
+    let mut first = fragment(&response, 0, 24, true);
+    let mut later = fragment(&response, 24, 48, false);
+
+    let mut early = later.clone();
+    assert_eq!(
+        inbound(&mut table, &mut early),
+        Err(NatError::UnknownFragment)
+    );
+    assert_eq!(early, later);
+
+    inbound(&mut table, &mut first).unwrap();
+    inbound(&mut table, &mut later).unwrap();
+    assert_eq!(dst_ip(&later), HOST_IP);
+
+    assert_eq!(table.stats().fragments_unmatched, 2);
+}
+
+#[test]
+fn fragment_flows_time_out() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+
+    let request = udp(HOST_IP, 40004, REMOTE_IP, 5060, &[0x55; 40]);
+    let mut first = fragment(&request, 0, 24, true);
+    outbound(&mut table, &mut first).unwrap();
+
+    clock.advance(table.config().fragments.timeout_ms as u64);
+    table.housekeeping();
+    assert_eq!(table.stats().fragment_flows, 0);
+
+    let mut later = fragment(&request, 24, 48, false);
+    let before = later.clone();
+    assert_eq!(
+        outbound(&mut table, &mut later),
+        Err(NatError::UnknownFragment)
+    );
+    assert_eq!(later, before);
+}
+
+#[test]
+fn other_protocols_map_address_only() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+    let other_host = [192, 168, 4, 3];
+
+    // GRE keepalive, the payload is not touched
+    let gre = [0x00, 0x00, 0x08, 0x00, 0xDE, 0xAD, 0xBE, 0xEF];
+    let mut request = ipv4(HOST_IP, REMOTE_IP, 47, &gre);
+    outbound(&mut table, &mut request).unwrap();
+    assert_eq!(src_ip(&request), EXTERNAL_IP);
+    assert_eq!(ip_checksum(&request[..20]), 0);
+    assert_eq!(request[20..], gre);
+
+    let mut reply = ipv4(REMOTE_IP, EXTERNAL_IP, 47, &gre);
+    inbound(&mut table, &mut reply).unwrap();
+    assert_eq!(dst_ip(&reply), HOST_IP);
+    assert_eq!(ip_checksum(&reply[..20]), 0);
+    assert_eq!(reply[20..], gre);
+
+    // Replies could not tell two hosts apart
+    let mut second = ipv4(other_host, REMOTE_IP, 47, &gre);
+    assert_eq!(
+        outbound(&mut table, &mut second),
+        Err(NatError::RemoteInUse)
+    );
+
+    // Another protocol or another remote is free
+    let mut sctp = ipv4(other_host, REMOTE_IP, 132, &[0x11; 16]);
+    outbound(&mut table, &mut sctp).unwrap();
+    let mut gre_elsewhere = ipv4(other_host, ROUTER_IP, 47, &gre);
+    outbound(&mut table, &mut gre_elsewhere).unwrap();
+
+    let stats = table.stats();
+    assert_eq!(stats.address_only, 3);
+    assert_eq!(stats.address_only_refused, 1);
+
+    // The remote is handed over once the mapping times out
+    clock.advance(table.config().timeouts.other_ms as u64 + 1);
+    table.housekeeping();
+    let mut second = ipv4(other_host, REMOTE_IP, 47, &gre);
+    outbound(&mut table, &mut second).unwrap();
+}
+
+#[test]
+fn udp_lite_checksum_follows_addresses() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+
+    let mut l4 = Vec::new();
+    l4.extend_from_slice(&40005u16.to_be_bytes());
+    l4.extend_from_slice(&5004u16.to_be_bytes());
+    // Checksum coverage 0: the whole datagram
+    l4.extend_from_slice(&[0, 0, 0, 0]);
+    l4.extend_from_slice(b"media");
+    let csum = l4_checksum(HOST_IP, REMOTE_IP, 136, &l4);
+    l4[6..8].copy_from_slice(&csum.to_be_bytes());
+
+    let mut pkt = ipv4(HOST_IP, REMOTE_IP, 136, &l4);
+    outbound(&mut table, &mut pkt).unwrap();
+    assert_eq!(src_ip(&pkt), EXTERNAL_IP);
+    assert_eq!(word(&pkt, 20), 40005);
+    assert_checksums(&pkt);
+}
+
+#[test]
+fn pptp_calls_translate_gre_call_ids() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+    let other_host = [192, 168, 4, 3];
+
+    // Two clients picking the same call ID towards one server
+    let mut external_ids = Vec::new();
+    let mut tcp_ports = Vec::new();
+    for (host, port) in [(HOST_IP, 40010), (other_host, 40011)] {
+        let mut request = pptp(host, port, REMOTE_IP, 1723, 7, 0x0001, 0);
+        outbound(&mut table, &mut request).unwrap();
+        assert_checksums(&request);
+        let external_id = word(&request, 40 + 12);
+        let tcp_port = word(&request, 20);
+
+        let mut reply = pptp(
+            REMOTE_IP,
+            1723,
+            EXTERNAL_IP,
+            tcp_port,
+            8,
+            0x0100 + port,
+            external_id,
+        );
+        inbound(&mut table, &mut reply).unwrap();
+        assert_checksums(&reply);
+        assert_eq!(word(&reply, 40 + 12), 0x0100 + port);
+        assert_eq!(word(&reply, 40 + 14), 0x0001);
+        external_ids.push(external_id);
+        tcp_ports.push(tcp_port);
+    }
+    assert_ne!(external_ids[0], external_ids[1]);
+
+    // Client GRE carries the server's call ID and keeps it
+    let mut data = gre(other_host, REMOTE_IP, 0x0100 + 40011);
+    outbound(&mut table, &mut data).unwrap();
+    assert_eq!(src_ip(&data), EXTERNAL_IP);
+    assert_eq!(word(&data, 26), 0x0100 + 40011);
+    assert_eq!(ip_checksum(&data[..20]), 0);
+
+    // Server GRE is told apart by the external call ID
+    for (host, external_id) in [(HOST_IP, external_ids[0]), (other_host, external_ids[1])] {
+        let mut data = gre(REMOTE_IP, EXTERNAL_IP, external_id);
+        inbound(&mut table, &mut data).unwrap();
+        assert_eq!(dst_ip(&data), host);
+        assert_eq!(word(&data, 26), 0x0001);
+        assert_eq!(ip_checksum(&data[..20]), 0);
+    }
+
+    // GRE of an unknown call is not let through
+    let mut stray = gre(REMOTE_IP, EXTERNAL_IP, external_ids[0] ^ 0x8000);
+    assert!(inbound(&mut table, &mut stray).is_err());
+
+    // The server ends the first call
+    let mut disconnect = pptp(
+        REMOTE_IP,
+        1723,
+        EXTERNAL_IP,
+        tcp_ports[0],
+        13,
+        0x0100 + 40010,
+        0,
+    );
+    inbound(&mut table, &mut disconnect).unwrap();
+    assert_eq!(dst_ip(&disconnect), HOST_IP);
+    assert_checksums(&disconnect);
+    let end = table.pop_session_end().unwrap();
+    assert_eq!(end.reason, SessionEndReason::Closed);
+    assert_eq!(end.entry.external_port, external_ids[0]);
+    let mut late = gre(REMOTE_IP, EXTERNAL_IP, external_ids[0]);
+    assert!(inbound(&mut table, &mut late).is_err());
+}
+
+#[test]
+fn esp_pairs_spis_per_host() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+    let other_host = [192, 168, 4, 3];
+
+    let mut first = esp(HOST_IP, REMOTE_IP, 0x1000_0001);
+    outbound(&mut table, &mut first).unwrap();
+    assert_eq!(src_ip(&first), EXTERNAL_IP);
+    assert_eq!(first[20..24], 0x1000_0001u32.to_be_bytes());
+    assert_eq!(ip_checksum(&first[..20]), 0);
+
+    // The first inbound SA from the remote pairs with the waiting one
+    let mut reply = esp(REMOTE_IP, EXTERNAL_IP, 0x2000_0001);
+    inbound(&mut table, &mut reply).unwrap();
+    assert_eq!(dst_ip(&reply), HOST_IP);
+
+    let mut second = esp(other_host, REMOTE_IP, 0x1000_0002);
+    outbound(&mut table, &mut second).unwrap();
+    let mut reply = esp(REMOTE_IP, EXTERNAL_IP, 0x2000_0002);
+    inbound(&mut table, &mut reply).unwrap();
+    assert_eq!(dst_ip(&reply), other_host);
+
+    // Each SA keeps going to its own host
+    for (spi, host) in [(0x2000_0001, HOST_IP), (0x2000_0002, other_host)] {
+        let mut pkt = esp(REMOTE_IP, EXTERNAL_IP, spi);
+        inbound(&mut table, &mut pkt).unwrap();
+        assert_eq!(dst_ip(&pkt), host);
+    }
+
+    // Nothing is waiting for a third SA
+    let mut unknown = esp(REMOTE_IP, EXTERNAL_IP, 0x2000_0003);
+    assert_eq!(inbound(&mut table, &mut unknown), Err(NatError::NoMapping));
+}
+
+#[test]
+fn esp_waits_while_several_hosts_await_an_sa() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+    let other_host = [192, 168, 4, 3];
+    let timeout = table.config().timeouts.other_ms as u64;
+
+    let mut first = esp(HOST_IP, REMOTE_IP, 0x1000_0001);
+    outbound(&mut table, &mut first).unwrap();
+    let mut second = esp(other_host, REMOTE_IP, 0x1000_0002);
+    outbound(&mut table, &mut second).unwrap();
+
+    // Either host could own the SA, it is not guessed
+    for spi in [0x2000_0001, 0x2000_0002] {
+        let mut reply = esp(REMOTE_IP, EXTERNAL_IP, spi);
+        assert_eq!(inbound(&mut table, &mut reply), Err(NatError::NoMapping));
+    }
+
+    // Once the other host's SA has timed out the reply finds its host
+    clock.advance(timeout / 2);
+    let mut first = esp(HOST_IP, REMOTE_IP, 0x1000_0001);
+    outbound(&mut table, &mut first).unwrap();
+    clock.advance(timeout / 2 + 1);
+    assert_eq!(table.housekeeping().expired, 1);
+
+    let mut reply = esp(REMOTE_IP, EXTERNAL_IP, 0x2000_0002);
+    inbound(&mut table, &mut reply).unwrap();
+    assert_eq!(dst_ip(&reply), HOST_IP);
+}
+
+#[test]
+fn ike_keeps_its_port_once_per_remote() {
+    let clock = MockClock::new(0);
+    let mut table = table(&clock);
+    let other_host = [192, 168, 4, 3];
+
+    let mut first = udp(HOST_IP, 500, REMOTE_IP, 500, b"sa_init");
+    outbound(&mut table, &mut first).unwrap();
+    assert_eq!(word(&first, 20), 500);
+    assert_checksums(&first);
+
+    // Another host's IKE to the same remote moves to another port
+    let mut second = udp(other_host, 500, REMOTE_IP, 500, b"sa_init");
+    outbound(&mut table, &mut second).unwrap();
+    let second_port = word(&second, 20);
+    assert_ne!(second_port, 500);
+
+    // ...but keeps 500 towards another remote
+    let mut third = udp(other_host, 500, ROUTER_IP, 500, b"sa_init");
+    outbound(&mut table, &mut third).unwrap();
+    assert_eq!(word(&third, 20), 500);
+
+    // Replies to the shared port go to the host talking to that remote
+    let mut reply = udp(REMOTE_IP, 500, EXTERNAL_IP, 500, b"sa_init");
+    inbound(&mut table, &mut reply).unwrap();
+    assert_eq!(dst_ip(&reply), HOST_IP);
+    let mut reply = udp(ROUTER_IP, 500, EXTERNAL_IP, 500, b"sa_init");
+    inbound(&mut table, &mut reply).unwrap();
+    assert_eq!(dst_ip(&reply), other_host);
+    let mut reply = udp(REMOTE_IP, 500, EXTERNAL_IP, second_port, b"sa_init");
+    inbound(&mut table, &mut reply).unwrap();
+    assert_eq!(dst_ip(&reply), other_host);
+    assert_eq!(word(&reply, 22), 500);
+}
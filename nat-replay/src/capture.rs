@@ -0,0 +1,499 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+use std::io::{self, Write};
+
+/// Link types understood by the reader
+pub const LINKTYPE_ETHERNET: u16 = 1;
+pub const LINKTYPE_RAW: u16 = 101;
+pub const LINKTYPE_LINUX_SLL: u16 = 113;
+pub const LINKTYPE_IPV4: u16 = 228;
+
+const PCAP_MAGIC_US: u32 = 0xA1B2_C3D4;
+const PCAP_MAGIC_NS: u32 = 0xA1B2_3C4D;
+
+const PCAPNG_SHB: u32 = 0x0A0D_0D0A;
+const PCAPNG_IDB: u32 = 0x0000_0001;
+const PCAPNG_SPB: u32 = 0x0000_0003;
+const PCAPNG_EPB: u32 = 0x0000_0006;
+const PCAPNG_BYTE_ORDER: u32 = 0x1A2B_3C4D;
+
+/// Option code of the interface timestamp resolution
+const IF_TSRESOL: u16 = 9;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88A8;
+
+/// One captured frame
+pub struct Frame<'a> {
+    /// Interface the frame was captured on, 0 for pcap files
+    pub interface: u32,
+    pub link_type: u16,
+    /// Capture time in microseconds
+    pub timestamp_us: u64,
+    /// Captured bytes, possibly cut to the snap length
+    pub data: &'a [u8],
+    /// Length of the frame on the wire
+    pub orig_len: u32,
+}
+
+/// Capture file format errors
+#[derive(Debug)]
+pub enum CaptureError {
+    /// Neither a pcap nor a pcapng file
+    UnknownFormat,
+    /// File ends inside a header or block
+    Truncated,
+    /// Packet refers to an interface not described before it
+    UnknownInterface(u32),
+}
+
+impl std::fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureError::UnknownFormat => write!(f, "not a pcap or pcapng file"),
+            CaptureError::Truncated => write!(f, "capture file is truncated"),
+            CaptureError::UnknownInterface(id) => write!(f, "undeclared interface {}", id),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Endian {
+    big: bool,
+}
+
+impl Endian {
+    fn u16(self, data: &[u8], off: usize) -> Option<u16> {
+        let bytes = data.get(off..off + 2)?.try_into().ok()?;
+        Some(if self.big {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(self, data: &[u8], off: usize) -> Option<u32> {
+        let bytes = data.get(off..off + 4)?.try_into().ok()?;
+        Some(if self.big {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+/// Interface described by a pcapng interface description block
+#[derive(Clone, Copy)]
+struct Interface {
+    link_type: u16,
+    /// Timestamp units per second
+    ticks_per_sec: u64,
+}
+
+/// Decode every frame of a pcap or pcapng file
+pub fn read_frames(file: &[u8]) -> Result<Vec<Frame<'_>>, CaptureError> {
+    let magic = file
+        .get(..4)
+        .map(|m| u32::from_le_bytes(m.try_into().unwrap()))
+        .ok_or(CaptureError::UnknownFormat)?;
+
+    match magic {
+        PCAPNG_SHB => read_pcapng(file),
+        m if [PCAP_MAGIC_US, PCAP_MAGIC_NS].contains(&m)
+            || [PCAP_MAGIC_US, PCAP_MAGIC_NS].contains(&m.swap_bytes()) =>
+        {
+            read_pcap(file)
+        }
+        _ => Err(CaptureError::UnknownFormat),
+    }
+}
+
+fn read_pcap(file: &[u8]) -> Result<Vec<Frame<'_>>, CaptureError> {
+    let le = u32::from_le_bytes(file[..4].try_into().unwrap());
+    let endian = Endian {
+        big: le != PCAP_MAGIC_US && le != PCAP_MAGIC_NS,
+    };
+    let nanos = endian.u32(file, 0) == Some(PCAP_MAGIC_NS);
+    // The upper bits of the link type field carry FCS information
+    let link_type = endian.u32(file, 20).ok_or(CaptureError::Truncated)? as u16;
+
+    let mut frames = Vec::new();
+    let mut off = 24;
+
+    while off < file.len() {
+        let hdr = file.get(off..off + 16).ok_or(CaptureError::Truncated)?;
+        let sec = endian.u32(hdr, 0).unwrap() as u64;
+        let frac = endian.u32(hdr, 4).unwrap() as u64;
+        let cap_len = endian.u32(hdr, 8).unwrap() as usize;
+        let orig_len = endian.u32(hdr, 12).unwrap();
+
+        let data = file
+            .get(off + 16..off + 16 + cap_len)
+            .ok_or(CaptureError::Truncated)?;
+
+        frames.push(Frame {
+            interface: 0,
+            link_type,
+            timestamp_us: sec * 1_000_000 + if nanos { frac / 1000 } else { frac },
+            data,
+            orig_len,
+        });
+        off += 16 + cap_len;
+    }
+
+    Ok(frames)
+}
+
+fn read_pcapng(file: &[u8]) -> Result<Vec<Frame<'_>>, CaptureError> {
+    let mut frames = Vec::new();
+    let mut interfaces: Vec<Interface> = Vec::new();
+    let mut endian = Endian { big: false };
+    let mut off = 0;
+
+    while off < file.len() {
+        // The section header type reads the same in both byte orders
+        let block_type = endian.u32(file, off).ok_or(CaptureError::Truncated)?;
+
+        // Each section header sets the byte order of its section
+        if block_type == PCAPNG_SHB {
+            let order = file.get(off + 8..off + 12).ok_or(CaptureError::Truncated)?;
+            endian.big = u32::from_be_bytes(order.try_into().unwrap()) == PCAPNG_BYTE_ORDER;
+            interfaces.clear();
+        }
+
+        let block_len = endian.u32(file, off + 4).ok_or(CaptureError::Truncated)? as usize;
+        if block_len < 12 || !block_len.is_multiple_of(4) {
+            return Err(CaptureError::Truncated);
+        }
+        let block = file
+            .get(off..off + block_len)
+            .ok_or(CaptureError::Truncated)?;
+        let body = &block[8..block_len - 4];
+
+        match block_type {
+            PCAPNG_IDB => interfaces.push(read_interface(body, endian)?),
+            PCAPNG_EPB => {
+                let id = endian.u32(body, 0).ok_or(CaptureError::Truncated)?;
+                let iface = interfaces
+                    .get(id as usize)
+                    .ok_or(CaptureError::UnknownInterface(id))?;
+                let ts_high = endian.u32(body, 4).ok_or(CaptureError::Truncated)? as u64;
+                let ts_low = endian.u32(body, 8).ok_or(CaptureError::Truncated)? as u64;
+                let cap_len = endian.u32(body, 12).ok_or(CaptureError::Truncated)? as usize;
+                let orig_len = endian.u32(body, 16).ok_or(CaptureError::Truncated)?;
+                let data = body.get(20..20 + cap_len).ok_or(CaptureError::Truncated)?;
+
+                let ticks = (ts_high << 32) | ts_low;
+                frames.push(Frame {
+                    interface: id,
+                    link_type: iface.link_type,
+                    timestamp_us: (ticks as u128 * 1_000_000 / iface.ticks_per_sec as u128) as u64,
+                    data,
+                    orig_len,
+                });
+            }
+            PCAPNG_SPB => {
+                let iface = interfaces
+                    .first()
+                    .ok_or(CaptureError::UnknownInterface(0))?;
+                let orig_len = endian.u32(body, 0).ok_or(CaptureError::Truncated)?;
+                let data = body.get(4..).ok_or(CaptureError::Truncated)?;
+                frames.push(Frame {
+                    interface: 0,
+                    link_type: iface.link_type,
+                    // Simple packet blocks carry no timestamp
+                    timestamp_us: frames.last().map_or(0, |f| f.timestamp_us),
+                    data: &data[..data.len().min(orig_len as usize)],
+                    orig_len,
+                });
+            }
+            _ => {}
+        }
+
+        off += block_len;
+    }
+
+    Ok(frames)
+}
+
+fn read_interface(body: &[u8], endian: Endian) -> Result<Interface, CaptureError> {
+    let link_type = endian.u16(body, 0).ok_or(CaptureError::Truncated)?;
+    let mut ticks_per_sec = 1_000_000;
+
+    // Options: code, length, value padded to 32 bits
+    let mut off = 8;
+    while let (Some(code), Some(len)) = (endian.u16(body, off), endian.u16(body, off + 2)) {
+        if code == 0 {
+            break;
+        }
+        if code == IF_TSRESOL {
+            if let Some(&res) = body.get(off + 4) {
+                let exp = (res & 0x7F) as u32;
+                ticks_per_sec = if res & 0x80 != 0 {
+                    1u64.checked_shl(exp).unwrap_or(u64::MAX)
+                } else {
+                    10u64.checked_pow(exp).unwrap_or(u64::MAX)
+                };
+            }
+        }
+        off += 4 + (len as usize).div_ceil(4) * 4;
+    }
+
+    Ok(Interface {
+        link_type,
+        ticks_per_sec: ticks_per_sec.max(1),
+    })
+}
+
+/// Offset of the IPv4 header in a frame, `None` for other traffic
+pub fn ipv4_offset(link_type: u16, data: &[u8]) -> Option<usize> {
+    let is_ipv4 = |off: usize| data.get(off).is_some_and(|b| b >> 4 == 4);
+
+    match link_type {
+        LINKTYPE_ETHERNET => {
+            let mut off = 12;
+            let mut ethertype = u16::from_be_bytes(data.get(off..off + 2)?.try_into().ok()?);
+            while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+                off += 4;
+                ethertype = u16::from_be_bytes(data.get(off..off + 2)?.try_into().ok()?);
+            }
+            (ethertype == ETHERTYPE_IPV4).then_some(off + 2)
+        }
+        LINKTYPE_LINUX_SLL => {
+            let proto = u16::from_be_bytes(data.get(14..16)?.try_into().ok()?);
+            (proto == ETHERTYPE_IPV4).then_some(16)
+        }
+        LINKTYPE_RAW | LINKTYPE_IPV4 => is_ipv4(0).then_some(0),
+        _ => None,
+    }
+}
+
+/// Writer of a pcap file holding raw IP packets
+pub struct PcapWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(&PCAP_MAGIC_US.to_le_bytes())?;
+        out.write_all(&2u16.to_le_bytes())?; // version 2.4
+        out.write_all(&4u16.to_le_bytes())?;
+        out.write_all(&0i32.to_le_bytes())?; // GMT offset
+        out.write_all(&0u32.to_le_bytes())?; // accuracy
+        out.write_all(&65535u32.to_le_bytes())?; // snap length
+        out.write_all(&(LINKTYPE_RAW as u32).to_le_bytes())?;
+        Ok(Self { out })
+    }
+
+    pub fn write(&mut self, timestamp_us: u64, packet: &[u8], orig_len: u32) -> io::Result<()> {
+        let sec = (timestamp_us / 1_000_000) as u32;
+        let usec = (timestamp_us % 1_000_000) as u32;
+        self.out.write_all(&sec.to_le_bytes())?;
+        self.out.write_all(&usec.to_le_bytes())?;
+        self.out.write_all(&(packet.len() as u32).to_le_bytes())?;
+        self.out.write_all(&orig_len.to_le_bytes())?;
+        self.out.write_all(packet)
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nat_core::testing::{udp, HOST_IP, REMOTE_IP};
+
+    /// pcap file of raw IP packets, records are (seconds, fraction, data)
+    fn pcap(big: bool, magic: u32, records: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let u16b = |v: u16| {
+            if big {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let u32b = |v: u32| {
+            if big {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+
+        let mut file = Vec::new();
+        file.extend_from_slice(&u32b(magic));
+        file.extend_from_slice(&u16b(2));
+        file.extend_from_slice(&u16b(4));
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&u32b(65535));
+        file.extend_from_slice(&u32b(LINKTYPE_RAW as u32));
+        for (sec, frac, data) in records {
+            file.extend_from_slice(&u32b(*sec));
+            file.extend_from_slice(&u32b(*frac));
+            file.extend_from_slice(&u32b(data.len() as u32));
+            file.extend_from_slice(&u32b(data.len() as u32));
+            file.extend_from_slice(data);
+        }
+        file
+    }
+
+    /// Little-endian pcapng block, `body` padded to 32 bits
+    fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let padded = body.len().div_ceil(4) * 4;
+        let len = (12 + padded) as u32;
+        let mut block = Vec::new();
+        block.extend_from_slice(&block_type.to_le_bytes());
+        block.extend_from_slice(&len.to_le_bytes());
+        block.extend_from_slice(body);
+        block.resize(8 + padded, 0);
+        block.extend_from_slice(&len.to_le_bytes());
+        block
+    }
+
+    fn section_header() -> Vec<u8> {
+        let mut body = PCAPNG_BYTE_ORDER.to_le_bytes().to_vec();
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&u64::MAX.to_le_bytes());
+        block(PCAPNG_SHB, &body)
+    }
+
+    fn interface(link_type: u16, tsresol: Option<u8>) -> Vec<u8> {
+        let mut body = link_type.to_le_bytes().to_vec();
+        body.extend_from_slice(&[0; 2]);
+        body.extend_from_slice(&65535u32.to_le_bytes());
+        if let Some(res) = tsresol {
+            body.extend_from_slice(&IF_TSRESOL.to_le_bytes());
+            body.extend_from_slice(&1u16.to_le_bytes());
+            body.extend_from_slice(&[res, 0, 0, 0]);
+            body.extend_from_slice(&[0; 4]);
+        }
+        block(PCAPNG_IDB, &body)
+    }
+
+    fn enhanced_packet(interface: u32, ticks: u64, data: &[u8]) -> Vec<u8> {
+        let mut body = interface.to_le_bytes().to_vec();
+        body.extend_from_slice(&((ticks >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(ticks as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(data);
+        block(PCAPNG_EPB, &body)
+    }
+
+    fn packet() -> Vec<u8> {
+        udp(HOST_IP, 40000, REMOTE_IP, 53, b"query")
+    }
+
+    #[test]
+    fn pcap_in_either_byte_order() {
+        let pkt = packet();
+        for big in [false, true] {
+            let file = pcap(
+                big,
+                PCAP_MAGIC_US,
+                &[(1, 250_000, &pkt), (2, 5, &pkt[..20])],
+            );
+            let frames = read_frames(&file).unwrap();
+
+            assert_eq!(frames.len(), 2);
+            assert_eq!(frames[0].link_type, LINKTYPE_RAW);
+            assert_eq!(frames[0].timestamp_us, 1_250_000);
+            assert_eq!(frames[0].data, &pkt[..]);
+            assert_eq!(frames[0].orig_len, pkt.len() as u32);
+            assert_eq!(frames[1].timestamp_us, 2_000_005);
+            assert_eq!(frames[1].data, &pkt[..20]);
+        }
+    }
+
+    #[test]
+    fn pcap_with_nanosecond_timestamps() {
+        let pkt = packet();
+        let file = pcap(false, PCAP_MAGIC_NS, &[(3, 123_456_789, &pkt)]);
+        let frames = read_frames(&file).unwrap();
+        assert_eq!(frames[0].timestamp_us, 3_123_456);
+    }
+
+    #[test]
+    fn pcap_cut_inside_a_record() {
+        let pkt = packet();
+        let file = pcap(false, PCAP_MAGIC_US, &[(1, 0, &pkt)]);
+        assert!(matches!(
+            read_frames(&file[..file.len() - 1]),
+            Err(CaptureError::Truncated)
+        ));
+        assert!(matches!(
+            read_frames(b"GIF89a"),
+            Err(CaptureError::UnknownFormat)
+        ));
+    }
+
+    #[test]
+    fn pcapng_follows_interface_resolution() {
+        let pkt = packet();
+        let mut file = section_header();
+        file.extend(interface(LINKTYPE_RAW, None));
+        // Nanoseconds, then 2^-10 s units
+        file.extend(interface(LINKTYPE_IPV4, Some(9)));
+        file.extend(interface(LINKTYPE_RAW, Some(0x80 | 10)));
+        file.extend(enhanced_packet(0, 1_500_000, &pkt));
+        file.extend(enhanced_packet(1, 2_000_000_123_456, &pkt));
+        file.extend(enhanced_packet(2, 1024 * 7 + 512, &pkt[..20]));
+
+        let frames = read_frames(&file).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].timestamp_us, 1_500_000);
+        assert_eq!(frames[0].data, &pkt[..]);
+        assert_eq!(frames[1].interface, 1);
+        assert_eq!(frames[1].link_type, LINKTYPE_IPV4);
+        assert_eq!(frames[1].timestamp_us, 2_000_000_123);
+        assert_eq!(frames[2].timestamp_us, 7_500_000);
+        assert_eq!(frames[2].data, &pkt[..20]);
+    }
+
+    #[test]
+    fn pcapng_packet_before_its_interface() {
+        let mut file = section_header();
+        file.extend(interface(LINKTYPE_RAW, None));
+        file.extend(enhanced_packet(1, 0, &packet()));
+        assert!(matches!(
+            read_frames(&file),
+            Err(CaptureError::UnknownInterface(1))
+        ));
+    }
+
+    #[test]
+    fn written_pcap_reads_back() {
+        let pkt = packet();
+        let mut file = Vec::new();
+        let mut writer = PcapWriter::new(&mut file).unwrap();
+        writer.write(4_000_017, &pkt, 1500).unwrap();
+        writer.finish().unwrap();
+
+        let frames = read_frames(&file).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].timestamp_us, 4_000_017);
+        assert_eq!(frames[0].data, &pkt[..]);
+        assert_eq!(frames[0].orig_len, 1500);
+    }
+
+    #[test]
+    fn ipv4_behind_vlan_tags() {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&ETHERTYPE_QINQ.to_be_bytes());
+        frame.extend_from_slice(&[0, 10]);
+        frame.extend_from_slice(&ETHERTYPE_VLAN.to_be_bytes());
+        frame.extend_from_slice(&[0, 20]);
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        frame.extend(packet());
+
+        assert_eq!(ipv4_offset(LINKTYPE_ETHERNET, &frame), Some(22));
+        assert_eq!(ipv4_offset(LINKTYPE_RAW, &packet()), Some(0));
+        assert_eq!(ipv4_offset(LINKTYPE_RAW, &[0x60; 40]), None);
+    }
+}
@@ -0,0 +1,399 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+//! Replays a pcap/pcapng capture through the NAT core.
+//!
+//! Each IPv4 frame is tagged as LAN or WAN ingress, translated by a
+//! `NatTable` whose clock follows the capture timestamps and written to a
+//! pcap of raw IP packets. Drops and checksum mismatches are reported.
+
+mod capture;
+mod verify;
+
+use capture::{Frame, PcapWriter};
+use nat_core::clock::MockClock;
+use nat_core::testing::{LAN, WAN};
+use nat_core::{Clock, NatConfig, NatTable, PacketContext};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::process::ExitCode;
+use verify::Verdict;
+
+/// Session capacity, the firmware default of CONFIG_NET_IPV4_NAT_MAX_ENTRIES
+const CAPACITY: usize = 128;
+
+/// Capture time between housekeeping runs, as the firmware task
+const HOUSEKEEPING_INTERVAL_MS: u64 = 1000;
+
+const USAGE: &str = "\
+usage: nat-replay [options] <input.pcap|pcapng> <output.pcap>
+
+  --internal <a.b.c.d/len>  LAN network (default 192.168.4.0/24)
+  --external <a.b.c.d>      WAN address of the router (required)
+  --ingress <lan|wan>       tag every frame with one direction
+  --lan-if <id>             pcapng interface captured on the LAN side
+  --wan-if <id>             pcapng interface captured on the WAN side
+  --verbose                 print the outcome of every frame
+
+Frames not tagged by --ingress or an interface option are LAN ingress
+when their source address is in the internal network, WAN otherwise.
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ingress {
+    Lan,
+    Wan,
+}
+
+struct Options {
+    input: String,
+    output: String,
+    internal_network: [u8; 4],
+    internal_netmask: [u8; 4],
+    external_ip: [u8; 4],
+    ingress: Option<Ingress>,
+    lan_ifaces: Vec<u32>,
+    wan_ifaces: Vec<u32>,
+    verbose: bool,
+}
+
+fn parse_ip(s: &str) -> Option<[u8; 4]> {
+    let mut ip = [0u8; 4];
+    let mut parts = s.split('.');
+    for byte in ip.iter_mut() {
+        *byte = parts.next()?.parse().ok()?;
+    }
+    parts.next().is_none().then_some(ip)
+}
+
+/// Parse `a.b.c.d/len` into a network and netmask
+fn parse_network(s: &str) -> Option<([u8; 4], [u8; 4])> {
+    let (ip, len) = s.split_once('/')?;
+    let len: u32 = len.parse().ok()?;
+    if len > 32 {
+        return None;
+    }
+    let mask = u32::MAX.checked_shl(32 - len).unwrap_or(0);
+    let network = u32::from_be_bytes(parse_ip(ip)?) & mask;
+    Some((network.to_be_bytes(), mask.to_be_bytes()))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut opts = Options {
+        input: String::new(),
+        output: String::new(),
+        internal_network: [192, 168, 4, 0],
+        internal_netmask: [255, 255, 255, 0],
+        external_ip: [0; 4],
+        ingress: None,
+        lan_ifaces: Vec::new(),
+        wan_ifaces: Vec::new(),
+        verbose: false,
+    };
+    let mut external = false;
+    let mut files = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+
+        match arg.as_str() {
+            "--internal" => {
+                let v = value("--internal")?;
+                (opts.internal_network, opts.internal_netmask) =
+                    parse_network(&v).ok_or_else(|| format!("bad network '{}'", v))?;
+            }
+            "--external" => {
+                let v = value("--external")?;
+                opts.external_ip = parse_ip(&v).ok_or_else(|| format!("bad address '{}'", v))?;
+                external = true;
+            }
+            "--ingress" => {
+                opts.ingress = match value("--ingress")?.as_str() {
+                    "lan" => Some(Ingress::Lan),
+                    "wan" => Some(Ingress::Wan),
+                    v => return Err(format!("bad direction '{}'", v)),
+                };
+            }
+            "--lan-if" | "--wan-if" => {
+                let v = value(&arg)?;
+                let id = v.parse().map_err(|_| format!("bad interface '{}'", v))?;
+                if arg == "--lan-if" {
+                    opts.lan_ifaces.push(id);
+                } else {
+                    opts.wan_ifaces.push(id);
+                }
+            }
+            "--verbose" => opts.verbose = true,
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+            _ => files.push(arg),
+        }
+    }
+
+    if !external {
+        return Err("--external is required".into());
+    }
+    match <[String; 2]>::try_from(files) {
+        Ok([input, output]) => {
+            opts.input = input;
+            opts.output = output;
+        }
+        Err(_) => return Err("expected an input and an output file".into()),
+    }
+
+    Ok(opts)
+}
+
+impl Options {
+    fn ingress(&self, frame: &Frame, packet: &[u8]) -> Ingress {
+        if let Some(ingress) = self.ingress {
+            return ingress;
+        }
+        if self.lan_ifaces.contains(&frame.interface) {
+            return Ingress::Lan;
+        }
+        if self.wan_ifaces.contains(&frame.interface) {
+            return Ingress::Wan;
+        }
+
+        let internal = packet[12..16]
+            .iter()
+            .zip(
+                self.internal_netmask
+                    .iter()
+                    .zip(self.internal_network.iter()),
+            )
+            .all(|(ip, (mask, net))| ip & mask == *net);
+        if internal {
+            Ingress::Lan
+        } else {
+            Ingress::Wan
+        }
+    }
+}
+
+/// Outcome counters of a replay
+#[derive(Default)]
+struct Report {
+    frames: u32,
+    translated: u32,
+    passed: u32,
+    drops: BTreeMap<String, u32>,
+    /// Checksums already wrong in the capture, usually TX offload
+    bad_input: u32,
+    /// Checksums valid in the capture but wrong after translation
+    bad_output: u32,
+    /// Frames whose L4 checksum could not be checked
+    partial: u32,
+}
+
+impl Report {
+    fn drop(&mut self, reason: String) {
+        *self.drops.entry(reason).or_default() += 1;
+    }
+
+    fn print(&self) {
+        let dropped: u32 = self.drops.values().sum();
+
+        println!("frames                      {}", self.frames);
+        println!("  translated                {}", self.translated);
+        println!("  passed unchanged          {}", self.passed);
+        println!("  dropped                   {}", dropped);
+        for (reason, count) in &self.drops {
+            println!("    {:<24}{}", reason, count);
+        }
+        println!("checksum errors in capture  {}", self.bad_input);
+        println!("checksum errors after NAT   {}", self.bad_output);
+        println!("checksums not checked       {}", self.partial);
+    }
+}
+
+fn replay(opts: &Options, frames: &[Frame], out: File) -> std::io::Result<Report> {
+    let clock = MockClock::new(0);
+    let config = NatConfig {
+        internal_network: opts.internal_network,
+        internal_netmask: opts.internal_netmask,
+        external_ip: opts.external_ip,
+        internal_iface: LAN,
+        external_iface: WAN,
+        ..NatConfig::default()
+    };
+    let mut table: NatTable<&MockClock, CAPACITY> = NatTable::new(config, &clock);
+    let mut writer = PcapWriter::new(BufWriter::new(out))?;
+    let mut report = Report::default();
+
+    let start_us = frames.first().map_or(0, |f| f.timestamp_us);
+    let mut next_housekeeping = HOUSEKEEPING_INTERVAL_MS;
+
+    for (n, frame) in frames.iter().enumerate() {
+        let n = n + 1;
+        report.frames += 1;
+
+        let off = match capture::ipv4_offset(frame.link_type, frame.data) {
+            Some(off) => off,
+            None => {
+                if opts.verbose {
+                    println!("frame {}: not IPv4, skipped", n);
+                }
+                report.drop("not IPv4".into());
+                continue;
+            }
+        };
+
+        // Drop link layer padding after the datagram
+        let mut packet = frame.data[off..].to_vec();
+        if let Some(len) = packet.get(2..4).map(|l| u16::from_be_bytes([l[0], l[1]])) {
+            packet.truncate(len as usize);
+        }
+
+        clock.set(frame.timestamp_us.saturating_sub(start_us) / 1000);
+        while clock.now_ms() >= next_housekeeping {
+            table.housekeeping();
+            next_housekeeping += HOUSEKEEPING_INTERVAL_MS;
+        }
+
+        let input = verify::check(&packet);
+        if let Verdict::Invalid(mismatch) = input {
+            println!("frame {}: {:?} checksum wrong in capture", n, mismatch);
+            report.bad_input += 1;
+        }
+
+        let mut ctx = match PacketContext::parse(&packet, core::ptr::null_mut()) {
+            Some(ctx) => ctx,
+            None => {
+                println!("frame {}: dropped, unparsable IPv4 header", n);
+                report.drop("unparsable".into());
+                continue;
+            }
+        };
+
+        let ingress = opts.ingress(frame, &packet);
+        let result = match ingress {
+            Ingress::Lan => table.translate_outbound(&mut ctx),
+            Ingress::Wan => table.translate_inbound(&mut ctx),
+        };
+
+        if let Err(e) = result {
+            println!("frame {} ({:?}): dropped, {:?}", n, ingress, e);
+            report.drop(format!("{:?}", e));
+            continue;
+        }
+
+        if ctx.needs_update {
+            ctx.apply(&mut packet);
+            report.translated += 1;
+        } else {
+            report.passed += 1;
+        }
+
+        match (input, verify::check(&packet)) {
+            (Verdict::Valid, Verdict::Invalid(mismatch)) => {
+                println!(
+                    "frame {} ({:?}): {:?} checksum wrong after translation",
+                    n, ingress, mismatch
+                );
+                report.bad_output += 1;
+            }
+            (_, Verdict::Partial) => report.partial += 1,
+            _ => {}
+        }
+
+        if opts.verbose {
+            println!(
+                "frame {} ({:?}): {} -> {}",
+                n,
+                ingress,
+                if ctx.needs_update {
+                    "translated"
+                } else {
+                    "unchanged"
+                },
+                format_endpoints(&packet)
+            );
+        }
+
+        let orig_len = frame
+            .orig_len
+            .saturating_sub(off as u32)
+            .max(packet.len() as u32);
+        writer.write(frame.timestamp_us, &packet, orig_len)?;
+    }
+
+    writer.finish()?;
+    Ok(report)
+}
+
+fn format_endpoints(packet: &[u8]) -> String {
+    let ip = |off: usize| {
+        format!(
+            "{}.{}.{}.{}",
+            packet[off],
+            packet[off + 1],
+            packet[off + 2],
+            packet[off + 3]
+        )
+    };
+    let ihl = ((packet[0] & 0x0F) as usize) * 4;
+    let port = |off: usize| {
+        packet
+            .get(ihl + off..ihl + off + 2)
+            .map_or(0, |p| u16::from_be_bytes([p[0], p[1]]))
+    };
+
+    match packet[9] {
+        6 | 17 => format!("{}:{} > {}:{}", ip(12), port(0), ip(16), port(2)),
+        _ => format!("{} > {}", ip(12), ip(16)),
+    }
+}
+
+fn main() -> ExitCode {
+    let opts = match parse_args(std::env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("nat-replay: {}", e);
+            }
+            eprint!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let file = match std::fs::read(&opts.input) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("nat-replay: {}: {}", opts.input, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let frames = match capture::read_frames(&file) {
+        Ok(frames) => frames,
+        Err(e) => {
+            eprintln!("nat-replay: {}: {}", opts.input, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let out = match File::create(&opts.output) {
+        Ok(out) => out,
+        Err(e) => {
+            eprintln!("nat-replay: {}: {}", opts.output, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let report = match replay(&opts, &frames, out) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("nat-replay: {}: {}", opts.output, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    report.print();
+
+    if report.bad_output > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+use nat_core::checksum::ip_checksum;
+
+/// Checksum found wrong in an IPv4 packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mismatch {
+    Ipv4Header,
+    Tcp,
+    Udp,
+    Icmp,
+}
+
+/// Result of checking the checksums of one packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Valid,
+    Invalid(Mismatch),
+    /// Header checksum is valid but the L4 checksum cannot be checked,
+    /// the packet is a fragment or was cut by the snap length
+    Partial,
+}
+
+/// Check the IPv4 header checksum and, when the whole datagram is
+/// present, the TCP, UDP or ICMP checksum
+pub fn check(pkt: &[u8]) -> Verdict {
+    let ihl = match pkt.first() {
+        Some(vhl) => ((vhl & 0x0F) as usize) * 4,
+        None => return Verdict::Partial,
+    };
+    let hdr = match pkt.get(..ihl) {
+        Some(hdr) if ihl >= 20 => hdr,
+        _ => return Verdict::Partial,
+    };
+
+    if ip_checksum(hdr) != 0 {
+        return Verdict::Invalid(Mismatch::Ipv4Header);
+    }
+
+    let total_len = u16::from_be_bytes([hdr[2], hdr[3]]) as usize;
+    let fragment = u16::from_be_bytes([hdr[6], hdr[7]]) & 0x3FFF != 0;
+    let l4 = match pkt.get(ihl..total_len) {
+        Some(l4) if !fragment => l4,
+        _ => return Verdict::Partial,
+    };
+
+    let (sum, mismatch) = match hdr[9] {
+        1 => (ip_checksum(l4), Mismatch::Icmp),
+        6 => (pseudo_checksum(hdr, 6, l4), Mismatch::Tcp),
+        // A zero UDP checksum means the sender did not compute one
+        17 if l4.get(6..8) == Some(&[0, 0]) => return Verdict::Valid,
+        17 => (pseudo_checksum(hdr, 17, l4), Mismatch::Udp),
+        _ => return Verdict::Valid,
+    };
+
+    if sum == 0 {
+        Verdict::Valid
+    } else {
+        Verdict::Invalid(mismatch)
+    }
+}
+
+/// Checksum over the pseudo header and the L4 segment
+fn pseudo_checksum(hdr: &[u8], proto: u8, l4: &[u8]) -> u16 {
+    let mut data = Vec::with_capacity(12 + l4.len());
+    data.extend_from_slice(&hdr[12..20]);
+    data.extend_from_slice(&[0, proto]);
+    data.extend_from_slice(&(l4.len() as u16).to_be_bytes());
+    data.extend_from_slice(l4);
+    ip_checksum(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nat_core::testing::{echo, fragment, tcp, udp, HOST_IP, REMOTE_IP};
+
+    #[test]
+    fn valid_packets() {
+        assert_eq!(
+            check(&udp(HOST_IP, 40000, REMOTE_IP, 53, b"q")),
+            Verdict::Valid
+        );
+        assert_eq!(
+            check(&tcp(HOST_IP, 40000, REMOTE_IP, 80, 0x02)),
+            Verdict::Valid
+        );
+        assert_eq!(check(&echo(HOST_IP, REMOTE_IP, 8, 7)), Verdict::Valid);
+    }
+
+    #[test]
+    fn bad_checksums() {
+        let mut pkt = udp(HOST_IP, 40000, REMOTE_IP, 53, b"query");
+        pkt[30] ^= 0x01;
+        assert_eq!(check(&pkt), Verdict::Invalid(Mismatch::Udp));
+
+        let mut pkt = tcp(HOST_IP, 40000, REMOTE_IP, 80, 0x02);
+        pkt[22] ^= 0x01;
+        assert_eq!(check(&pkt), Verdict::Invalid(Mismatch::Tcp));
+
+        let mut pkt = echo(HOST_IP, REMOTE_IP, 8, 7);
+        pkt[27] ^= 0x01;
+        assert_eq!(check(&pkt), Verdict::Invalid(Mismatch::Icmp));
+
+        let mut pkt = udp(HOST_IP, 40000, REMOTE_IP, 53, b"query");
+        pkt[8] -= 1;
+        assert_eq!(check(&pkt), Verdict::Invalid(Mismatch::Ipv4Header));
+    }
+
+    #[test]
+    fn zero_udp_checksum_is_not_checked() {
+        let mut pkt = udp(HOST_IP, 40000, REMOTE_IP, 53, b"query");
+        pkt[26..28].copy_from_slice(&[0, 0]);
+        assert_eq!(check(&pkt), Verdict::Valid);
+    }
+
+    #[test]
+    fn fragments_and_cut_packets_are_partial() {
+        let pkt = udp(HOST_IP, 40000, REMOTE_IP, 53, &[0x42; 32]);
+        assert_eq!(check(&fragment(&pkt, 0, 16, true)), Verdict::Partial);
+        assert_eq!(check(&pkt[..30]), Verdict::Partial);
+        assert_eq!(check(&[]), Verdict::Partial);
+    }
+}
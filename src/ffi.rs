@@ -41,10 +41,7 @@ pub struct NetContext {
     _private: [u8; 0],
 }
 
-#[repr(C)]
-pub struct NetIf {
-    _private: [u8; 0],
-}
+pub use nat_core::NetIf;
 
 #[repr(C)]
 pub struct NetPkt {
@@ -57,38 +54,38 @@ pub struct NetPkt {
 }
 
 impl NetPkt {
+    /// First buffer of the packet data
+    ///
+    /// # Safety
+    ///
+    /// `self` must be a packet allocated by the network stack, not a
+    /// packet whose `buffer` union member is in use.
     #[inline(always)]
     pub unsafe fn frags(&self) -> *mut NetBuf {
         self.frags_or_buffer
     }
 
+    /// Interface the packet is bound to
+    ///
+    /// # Safety
+    ///
+    /// `self` must be a packet allocated by the network stack.
     #[inline(always)]
     pub unsafe fn iface(&self) -> *mut NetIf {
         self.iface
     }
 
+    /// Bind the packet to another interface
+    ///
+    /// # Safety
+    ///
+    /// `iface` must be null or a live interface of the network stack.
     #[inline(always)]
     pub unsafe fn set_iface(&mut self, iface: *mut NetIf) {
         self.iface = iface;
     }
 }
 
-/// IPv4 header structure (matching Zephyr's net_ipv4_hdr)
-#[repr(C, packed)]
-#[derive(Clone, Copy)]
-pub struct Ipv4Hdr {
-    pub vhl: u8,         // Version + IHL
-    pub tos: u8,         // Type of service
-    pub len: [u8; 2],    // Total length (big-endian)
-    pub id: [u8; 2],     // Identification
-    pub offset: [u8; 2], // Flags + fragment offset
-    pub ttl: u8,         // Time to live
-    pub proto: u8,       // Protocol (TCP=6, UDP=17, ICMP=1)
-    pub chksum: u16,     // Header checksum
-    pub src: [u8; 4],    // Source IP
-    pub dst: [u8; 4],    // Destination IP
-}
-
 #[cfg(CONFIG_TIMEOUT_64BIT)]
 pub type KtickT = i64;
 
@@ -98,4 +95,17 @@ pub type KtickT = u32;
 extern "C" {
     /// packet send interface
     pub fn net_try_send_data(pkt: *mut NetPkt, timeout: KtickT) -> i32;
+
+    /// Take and give the NAT table mutex (nat.c)
+    pub fn nat_table_lock();
+    pub fn nat_table_unlock();
+
+    /// Whether a local IPv4 socket is bound to the port (nat.c)
+    pub fn nat_local_socket(proto: u8, port: u16) -> bool;
+}
+
+#[cfg(CONFIG_NET_IPV4_NAT_PMP)]
+extern "C" {
+    /// Multicast the new external address to NAT-PMP/PCP clients (pmp.c)
+    pub fn nat_pmp_announce();
 }
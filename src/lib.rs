@@ -24,6 +24,7 @@ use crate::wifi::Wifi;
 
 mod ffi;
 mod nat;
+mod netbuf;
 mod packet;
 
 mod pin;
@@ -38,6 +39,9 @@ const VERSION_MINOR: &str = env!("VERSION_MINOR");
 const PATCHLEVEL: &str = env!("PATCHLEVEL");
 const EXTRAVERSION: &str = env!("EXTRAVERSION");
 
+const NAT_HOUSEKEEPING_INTERVAL_MS: u64 =
+    zephyr::kconfig::CONFIG_NET_IPV4_NAT_HOUSEKEEPING_INTERVAL as u64;
+
 #[embassy_executor::task]
 async fn led_task(_spawner: Spawner) {
     let red_led_pin = RED_LED_PIN.get();
@@ -54,6 +58,33 @@ async fn led_task(_spawner: Spawner) {
     }
 }
 
+#[embassy_executor::task]
+async fn nat_task(_spawner: Spawner) {
+    let mut last: Option<nat::NatStats> = None;
+    loop {
+        if let Some(stats) = nat::housekeeping() {
+            if last != Some(stats) {
+                log::info!(
+                    "[NAT STATS] {} / {} (Peak {}), expired {}, evicted {}, events dropped {}, \
+                     fragments {} (unmatched {}), address-only {} (refused {})",
+                    stats.current,
+                    stats.capacity,
+                    stats.peak,
+                    stats.expired,
+                    stats.evicted,
+                    stats.events_dropped,
+                    stats.fragment_flows,
+                    stats.fragments_unmatched,
+                    stats.address_only,
+                    stats.address_only_refused
+                );
+                last = Some(stats);
+            }
+        }
+        Timer::after(Duration::from_millis(NAT_HOUSEKEEPING_INTERVAL_MS)).await;
+    }
+}
+
 #[no_mangle]
 extern "C" fn rust_main() {
     let _ = usage::set_logger();
@@ -69,5 +100,6 @@ extern "C" fn rust_main() {
     let executor = EXECUTOR_MAIN.init(Executor::new());
     executor.run(|spawner| {
         spawner.spawn(led_task(spawner)).unwrap();
+        spawner.spawn(nat_task(spawner)).unwrap();
     })
 }
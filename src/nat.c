@@ -0,0 +1,58 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+#include <zephyr/kernel.h>
+#include <zephyr/net/net_context.h>
+
+/* Serialises the NAT table between the packet path, the configuration
+ * calls and the NAT-PMP, UPnP and housekeeping threads. Interrupts keep
+ * running while a thread holds it.
+ */
+static K_MUTEX_DEFINE(nat_table_mutex);
+
+void nat_table_lock(void)
+{
+    k_mutex_lock(&nat_table_mutex, K_FOREVER);
+}
+
+void nat_table_unlock(void)
+{
+    k_mutex_unlock(&nat_table_mutex);
+}
+
+struct local_socket {
+    uint8_t proto;
+    uint16_t port;
+    bool found;
+};
+
+static void match_local_socket(struct net_context *context, void *user_data)
+{
+    struct local_socket *query = user_data;
+
+    if (net_context_get_family(context) != AF_INET ||
+        net_context_get_proto(context) != query->proto) {
+        return;
+    }
+
+    if (ntohs(net_sin_ptr(&context->local)->sin_port) == query->port) {
+        query->found = true;
+    }
+}
+
+/* Whether the router's own stack has an IPv4 socket bound to the port,
+ * keeping replies to it out of the DMZ.
+ */
+bool nat_local_socket(uint8_t proto, uint16_t port)
+{
+    struct local_socket query = {
+        .proto = proto,
+        .port = port,
+        .found = false,
+    };
+
+    net_context_foreach(match_local_socket, &query);
+
+    return query.found;
+}
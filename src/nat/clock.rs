@@ -0,0 +1,16 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+use nat_core::Clock;
+use zephyr::raw::k_uptime_get;
+
+/// Zephyr uptime, 64 bit so it does not wrap in practice
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZephyrClock;
+
+impl Clock for ZephyrClock {
+    fn now_ms(&self) -> u64 {
+        unsafe { k_uptime_get() as u64 }
+    }
+}
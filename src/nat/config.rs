@@ -0,0 +1,100 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+#![allow(unexpected_cfgs)]
+
+use super::entry::Protocol;
+use super::fragment::FragmentPolicy;
+use super::quota::Quota;
+use super::table::{EvictionPolicy, FilteringBehavior, MappingBehavior};
+use super::timeout::{seconds, TimeoutPolicy, DNS_PORT, IPSEC_NAT_T_PORT};
+use super::{NatConfig, MAX_TIMEOUT_OVERRIDES};
+use zephyr::kconfig;
+
+fn mapping() -> MappingBehavior {
+    if cfg!(CONFIG_NET_IPV4_NAT_MAPPING_EIM) {
+        MappingBehavior::EndpointIndependent
+    } else if cfg!(CONFIG_NET_IPV4_NAT_MAPPING_ADM) {
+        MappingBehavior::AddressDependent
+    } else {
+        MappingBehavior::AddressAndPortDependent
+    }
+}
+
+fn filtering() -> FilteringBehavior {
+    if cfg!(CONFIG_NET_IPV4_NAT_FILTERING_EIF) {
+        FilteringBehavior::EndpointIndependent
+    } else if cfg!(CONFIG_NET_IPV4_NAT_FILTERING_ADF) {
+        FilteringBehavior::AddressDependent
+    } else {
+        FilteringBehavior::AddressAndPortDependent
+    }
+}
+
+fn eviction() -> EvictionPolicy {
+    if cfg!(CONFIG_NET_IPV4_NAT_EVICTION_NONE) {
+        EvictionPolicy::Disabled
+    } else if cfg!(CONFIG_NET_IPV4_NAT_EVICTION_LRU) {
+        EvictionPolicy::LeastRecentlyUsed
+    } else {
+        EvictionPolicy::TransitoryFirst
+    }
+}
+
+fn timeouts() -> TimeoutPolicy<MAX_TIMEOUT_OVERRIDES> {
+    let mut policy = TimeoutPolicy {
+        tcp_established_ms: seconds(kconfig::CONFIG_NET_IPV4_NAT_TIMEOUT_TCP_ESTABLISHED as u32),
+        tcp_transitory_ms: seconds(kconfig::CONFIG_NET_IPV4_NAT_TIMEOUT_TCP_TRANSITORY as u32),
+        tcp_time_wait_ms: seconds(kconfig::CONFIG_NET_IPV4_NAT_TIMEOUT_TCP_TIME_WAIT as u32),
+        tcp_closed_ms: seconds(kconfig::CONFIG_NET_IPV4_NAT_TIMEOUT_TCP_CLOSED as u32),
+        udp_ms: seconds(kconfig::CONFIG_NET_IPV4_NAT_UDP_TIMEOUT as u32),
+        icmp_ms: seconds(kconfig::CONFIG_NET_IPV4_NAT_TIMEOUT_ICMP as u32),
+        other_ms: seconds(kconfig::CONFIG_NET_IPV4_NAT_TIMEOUT_OTHER as u32),
+        port_overrides: Default::default(),
+    };
+
+    let defaults = [
+        (DNS_PORT, kconfig::CONFIG_NET_IPV4_NAT_TIMEOUT_DNS as u32),
+        (
+            IPSEC_NAT_T_PORT,
+            kconfig::CONFIG_NET_IPV4_NAT_TIMEOUT_IPSEC_NAT_T as u32,
+        ),
+    ];
+    for (port, timeout) in defaults {
+        if timeout != 0 {
+            let _ = policy.set_port_override(Protocol::Udp, port, Some(seconds(timeout)));
+        }
+    }
+
+    policy
+}
+
+/// Whether the router's stack has a socket bound to the port
+fn local_socket(proto: Protocol, port: u16) -> bool {
+    unsafe { crate::ffi::nat_local_socket(proto.number(), port) }
+}
+
+/// NAT configuration built from the Kconfig defaults, addresses and
+/// interfaces are filled in by `nat_configure`
+pub fn from_kconfig() -> NatConfig {
+    NatConfig {
+        mapping: mapping(),
+        filtering: filtering(),
+        local_socket: Some(local_socket),
+        hairpinning: cfg!(CONFIG_NET_IPV4_NAT_HAIRPINNING),
+        eviction: eviction(),
+        quota: Quota::from_limits(
+            kconfig::CONFIG_NET_IPV4_NAT_HOST_QUOTA as u16,
+            kconfig::CONFIG_NET_IPV4_NAT_HOST_QUOTA_TCP as u16,
+            kconfig::CONFIG_NET_IPV4_NAT_HOST_QUOTA_UDP as u16,
+            kconfig::CONFIG_NET_IPV4_NAT_HOST_QUOTA_ICMP as u16,
+        ),
+        timeouts: timeouts(),
+        fragments: FragmentPolicy {
+            max_flows: kconfig::CONFIG_NET_IPV4_NAT_FRAGMENT_FLOWS as usize,
+            timeout_ms: seconds(kconfig::CONFIG_NET_IPV4_NAT_FRAGMENT_TIMEOUT as u32),
+        },
+        ..NatConfig::default()
+    }
+}
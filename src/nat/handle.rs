@@ -0,0 +1,86 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+use super::clock::ZephyrClock;
+use super::{config, NatTable};
+use crate::ffi::{nat_table_lock, nat_table_unlock};
+use core::cell::RefCell;
+use static_cell::StaticCell;
+
+/// Storage of the table, built in place when the NAT is first configured
+static TABLE: StaticCell<NatTable> = StaticCell::new();
+
+/// NAT table shared by the packet path, configuration calls and housekeeping
+///
+/// The table is only reachable inside `lock`, which holds the table mutex
+/// (nat.c) for the duration of the closure. Closures must stay short. The
+/// mutex is recursive, so a thread locking the handle again from inside a
+/// closure is refused rather than handed a second borrow.
+pub struct NatHandle {
+    table: RefCell<Option<&'static mut NatTable>>,
+}
+
+// The cell is only borrowed with the table mutex held
+unsafe impl Sync for NatHandle {}
+
+impl NatHandle {
+    pub const fn new() -> Self {
+        Self {
+            table: RefCell::new(None),
+        }
+    }
+
+    /// Run `f` on the table, `None` until the table has been created or
+    /// when this thread is already using it
+    pub fn lock<R>(&self, f: impl FnOnce(&mut NatTable) -> R) -> Option<R> {
+        self.with_table(|table| table.as_deref_mut().map(f))
+    }
+
+    /// Run `f` on the table, creating it first if needed, `None` when this
+    /// thread is already using it
+    pub fn lock_or_init<R>(&self, f: impl FnOnce(&mut NatTable) -> R) -> Option<R> {
+        self.with_table(|table| {
+            Some(f(table.get_or_insert_with(|| {
+                TABLE.init_with(|| NatTable::new(config::from_kconfig(), ZephyrClock))
+            })))
+        })
+    }
+
+    fn with_table<R>(
+        &self,
+        f: impl FnOnce(&mut Option<&'static mut NatTable>) -> Option<R>,
+    ) -> Option<R> {
+        let result = {
+            let _guard = Guard::take();
+            self.table.try_borrow_mut().map(|mut table| f(&mut table))
+        };
+
+        match result {
+            Ok(result) => result,
+            Err(_) => {
+                log::error!("[NAT] table locked again by the thread using it");
+                None
+            }
+        }
+    }
+}
+
+/// Holds the table mutex until dropped
+struct Guard;
+
+impl Guard {
+    fn take() -> Self {
+        unsafe { nat_table_lock() };
+        Guard
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        unsafe { nat_table_unlock() };
+    }
+}
+
+/// The NAT instance of the router
+pub static NAT: NatHandle = NatHandle::new();
@@ -2,18 +2,57 @@
 // SPDX-License-Identifier: Apache-2.0
 // Coskun ERGAN <coskunergan@gmail.com>
 
-pub mod checksum;
-pub mod entry;
-pub mod table;
+#![allow(unexpected_cfgs)]
 
-pub use table::NatTable;
+pub mod clock;
+pub mod config;
+pub mod handle;
+#[cfg(CONFIG_NET_IPV4_NAT_PMP)]
+pub mod pmp;
+#[cfg(CONFIG_NET_IPV4_NAT_UPNP)]
+pub mod upnp;
+
+use handle::NAT;
+pub use nat_core::{entry, forward, fragment, quota, table, timeout};
+pub use nat_core::{NatStats, SessionEnd};
 
 use crate::ffi::*;
-use crate::packet::PacketContext;
+use crate::packet;
+
+/// Session capacity of the NAT table
+const MAX_NAT_ENTRIES: usize = zephyr::kconfig::CONFIG_NET_IPV4_NAT_MAX_ENTRIES as usize;
+
+/// Port forwarding rules and leases held at once
+const MAX_PORT_FORWARDS: usize = zephyr::kconfig::CONFIG_NET_IPV4_NAT_MAX_PORT_FORWARDS as usize;
+
+/// Hosts with their own mapping quota
+const MAX_QUOTA_OVERRIDES: usize =
+    zephyr::kconfig::CONFIG_NET_IPV4_NAT_MAX_QUOTA_OVERRIDES as usize;
+
+/// Per-port idle timeout overrides
+const MAX_TIMEOUT_OVERRIDES: usize =
+    zephyr::kconfig::CONFIG_NET_IPV4_NAT_MAX_TIMEOUT_OVERRIDES as usize;
+
+/// NAT table of the router
+pub type NatTable = nat_core::NatTable<
+    clock::ZephyrClock,
+    MAX_NAT_ENTRIES,
+    MAX_PORT_FORWARDS,
+    MAX_QUOTA_OVERRIDES,
+    MAX_TIMEOUT_OVERRIDES,
+>;
 
-const NAT_TIMEOUT: KtickT = zephyr::kconfig::CONFIG_NET_IPV4_NAT_TIMEOUT as KtickT;
+/// NAT configuration of the router
+pub type NatConfig = nat_core::NatConfig<MAX_TIMEOUT_OVERRIDES>;
 
-static mut NAT_TABLE: Option<NatTable> = None;
+/// Ticks to wait for TX buffers when sending a translated packet,
+/// rounded up like `K_MSEC`
+const NAT_SEND_TIMEOUT: KtickT = (zephyr::kconfig::CONFIG_NET_IPV4_NAT_TIMEOUT as u64
+    * zephyr::kconfig::CONFIG_SYS_CLOCK_TICKS_PER_SEC as u64)
+    .div_ceil(1000) as KtickT;
+
+/// Session ends reported per housekeeping run
+const SESSION_END_BATCH: usize = 16;
 
 #[no_mangle]
 fn nat_outbound(pkt: *mut NetPkt) -> i32 {
@@ -22,38 +61,38 @@ fn nat_outbound(pkt: *mut NetPkt) -> i32 {
         return -1;
     }
 
-    let table = match unsafe { core::ptr::addr_of_mut!(NAT_TABLE).as_mut().unwrap() } {
-        Some(t) => t,
+    // Parse packet
+    let mut ctx = match unsafe { packet::from_pkt(pkt) } {
+        Some(c) => c,
         None => {
-            log::error!("[NAT] outbound: NAT table not initialized");
+            log::error!("[NAT] outbound: failed to parse packet");
             return -1;
         }
     };
 
-    // Parse packet
-    let mut ctx = match PacketContext::from_pkt(pkt) {
-        Some(c) => c,
+    // Perform NAT translation
+    let result = match NAT.lock(|table| table.translate_outbound(&mut ctx)) {
+        Some(r) => r,
         None => {
-            log::error!("[NAT] outbound: failed to parse packet");
+            log::error!("[NAT] outbound: NAT table not initialized");
             return -1;
         }
     };
 
-    // Perform NAT translation
-    match table.translate_outbound(&mut ctx) {
+    match result {
         Ok(_) => {
             // *** CRITICAL: Only apply if needs_update is true ***
             if ctx.needs_update {
                 log::info!("[NAT] outbound: Applying changes to packet");
-                ctx.apply_to_pkt(pkt);
+                unsafe { packet::apply_to_pkt(&ctx, pkt) };
                 return 1;
             } else {
                 log::info!("[NAT] outbound: No changes needed, packet unchanged");
             }
             0
         }
-        Err(_) => {
-            log::error!("[NAT] outbound: translation failed");
+        Err(e) => {
+            log::error!("[NAT] outbound: translation failed ({:?})", e);
             -1
         }
     }
@@ -67,30 +106,30 @@ fn nat_inbound(pkt: *mut NetPkt) -> i32 {
         return -1;
     }
 
-    let table = match unsafe { core::ptr::addr_of_mut!(NAT_TABLE).as_mut().unwrap() } {
-        Some(t) => t,
+    // Parse packet
+    let mut ctx = match unsafe { packet::from_pkt(pkt) } {
+        Some(c) => c,
         None => {
-            log::error!("[NAT] inbound: NAT table not initialized");
+            log::error!("[NAT] inbound: failed to parse packet");
             return -1;
         }
     };
 
-    // Parse packet
-    let mut ctx = match PacketContext::from_pkt(pkt) {
-        Some(c) => c,
+    // // Perform NAT translation
+    let result = match NAT.lock(|table| table.translate_inbound(&mut ctx)) {
+        Some(r) => r,
         None => {
-            log::error!("[NAT] inbound: failed to parse packet");
+            log::error!("[NAT] inbound: NAT table not initialized");
             return -1;
         }
     };
 
-    // // Perform NAT translation
-    match table.translate_inbound(&mut ctx) {
+    match result {
         Ok(_) => {
             // *** CRITICAL: Only apply if needs_update is true ***
             if ctx.needs_update {
                 log::info!("[NAT] inbound: Applying changes to packet");
-                ctx.apply_to_pkt(pkt);
+                unsafe { packet::apply_to_pkt(&ctx, pkt) };
                 return 1;
             } else {
                 log::info!("[NAT] inbound: No changes needed, packet unchanged");
@@ -135,7 +174,7 @@ pub unsafe extern "C" fn nat_hook(pkt: *mut NetPkt) -> i32 {
         1 => {
             // Packet was modified by inbound NAT, send it
             log::info!("[NAT] hook: inbound translation applied, sending packet");
-            net_try_send_data(pkt, NAT_TIMEOUT);
+            net_try_send_data(pkt, NAT_SEND_TIMEOUT);
             return 1;
         }
         -1 => {
@@ -151,7 +190,7 @@ pub unsafe extern "C" fn nat_hook(pkt: *mut NetPkt) -> i32 {
                 1 => {
                     // Packet was modified by outbound NAT, send it
                     log::info!("[NAT] hook: outbound translation applied, sending packet");
-                    net_try_send_data(pkt, NAT_TIMEOUT);
+                    net_try_send_data(pkt, NAT_SEND_TIMEOUT);
                     return 1;
                 }
                 0 => {
@@ -177,6 +216,46 @@ pub unsafe extern "C" fn nat_hook(pkt: *mut NetPkt) -> i32 {
     }
 }
 
+/// Periodic table maintenance, `None` until the NAT is configured
+///
+/// Expires sessions and leases under the same lock as the packet path,
+/// then logs the sessions that ended since the last run.
+pub fn housekeeping() -> Option<NatStats> {
+    let mut ended: heapless::Vec<SessionEnd, SESSION_END_BATCH> = heapless::Vec::new();
+
+    let stats = NAT.lock(|table| {
+        let stats = table.housekeeping();
+        while !ended.is_full() {
+            match table.pop_session_end() {
+                Some(end) => {
+                    let _ = ended.push(end);
+                }
+                None => break,
+            }
+        }
+        stats
+    })?;
+
+    for end in &ended {
+        let entry = &end.entry;
+        log::info!(
+            "[NAT] session end ({:?}): {:?} {:?}:{} <-> {:?}:{} via :{}, {:?}, lasted {} ms, idle {} ms",
+            end.reason,
+            entry.protocol,
+            entry.internal_ip,
+            entry.internal_port,
+            entry.remote_ip,
+            entry.remote_port,
+            entry.external_port,
+            entry.tcp_state,
+            end.ended_at.saturating_sub(entry.created),
+            end.ended_at.saturating_sub(entry.last_activity)
+        );
+    }
+
+    Some(stats)
+}
+
 #[no_mangle]
 pub extern "C" fn nat_configure(
     internal_net: *const u8,
@@ -185,37 +264,408 @@ pub extern "C" fn nat_configure(
     internal_iface: *mut NetIf,
     external_iface: *mut NetIf,
 ) -> i32 {
+    if internal_net.is_null() || internal_mask.is_null() || external_ip.is_null() {
+        return -1;
+    }
+
+    let mut network = [0u8; 4];
+    let mut netmask = [0u8; 4];
+    let mut address = [0u8; 4];
     unsafe {
-        let nat_table = core::ptr::addr_of_mut!(NAT_TABLE).as_mut().unwrap();
+        core::ptr::copy_nonoverlapping(internal_net, network.as_mut_ptr(), 4);
+        core::ptr::copy_nonoverlapping(internal_mask, netmask.as_mut_ptr(), 4);
+        core::ptr::copy_nonoverlapping(external_ip, address.as_mut_ptr(), 4);
+    }
+
+    log::info!(
+        "[NAT CFG] Internal: {:p}, External: {:p}",
+        internal_iface,
+        external_iface
+    );
+
+    let external_changed = NAT.lock_or_init(|table| {
+        // Keep runtime options, only the addressing is refreshed here
+        let mut config = table.config().clone();
+        config.internal_network = network;
+        config.internal_netmask = netmask;
+        config.external_ip = address;
+        config.internal_iface = internal_iface;
+        config.external_iface = external_iface;
 
-        if nat_table.is_none() {
-            NAT_TABLE = Some(NatTable::new());
+        let external_changed = config.external_ip != table.config().external_ip;
+        table.set_config(config);
+        external_changed
+    });
+
+    let external_changed = match external_changed {
+        Some(changed) => changed,
+        None => return -1,
+    };
+
+    // Tell NAT-PMP/PCP clients to refresh their mappings
+    #[cfg(CONFIG_NET_IPV4_NAT_PMP)]
+    if external_changed {
+        unsafe { nat_pmp_announce() };
+    }
+    #[cfg(not(CONFIG_NET_IPV4_NAT_PMP))]
+    let _ = external_changed;
+
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn nat_port_forward_add(
+    proto: u8,
+    external_start: u16,
+    external_end: u16,
+    internal_ip: *const u8,
+    internal_port: u16,
+) -> i32 {
+    let protocol = match entry::Protocol::from_u8(proto) {
+        Some(p) => p,
+        None => return -1,
+    };
+
+    if internal_ip.is_null() {
+        return -1;
+    }
+
+    let mut rule = forward::PortForward {
+        protocol,
+        external_start,
+        external_end,
+        internal_ip: [0; 4],
+        internal_port,
+        lease: None,
+    };
+    unsafe {
+        core::ptr::copy_nonoverlapping(internal_ip, rule.internal_ip.as_mut_ptr(), 4);
+    }
+
+    match NAT.lock(|table| table.add_port_forward(rule)) {
+        Some(Ok(())) => 0,
+        Some(Err(e)) => {
+            log::error!("[NAT] port forward rejected ({:?})", e);
+            -1
         }
+        None => -1,
+    }
+}
 
-        let table = match nat_table {
-            Some(t) => t,
-            None => return -1,
-        };
+#[no_mangle]
+pub extern "C" fn nat_port_forward_remove(proto: u8, external_start: u16) -> i32 {
+    let protocol = match entry::Protocol::from_u8(proto) {
+        Some(p) => p,
+        None => return -1,
+    };
 
-        if internal_net.is_null() || internal_mask.is_null() || external_ip.is_null() {
-            return -1;
+    match NAT.lock(|table| table.remove_port_forward(protocol, external_start)) {
+        Some(true) => 0,
+        _ => -1,
+    }
+}
+
+/// Number of sessions evicted to make room in a full table
+#[no_mangle]
+pub extern "C" fn nat_evictions() -> u32 {
+    NAT.lock(|table| table.evictions()).unwrap_or(0)
+}
+
+/// Override the mapping quota of an internal host, 0 means unlimited
+#[no_mangle]
+pub extern "C" fn nat_host_quota_set(
+    host_ip: *const u8,
+    total: u16,
+    tcp: u16,
+    udp: u16,
+    icmp: u16,
+) -> i32 {
+    if host_ip.is_null() {
+        return -1;
+    }
+
+    let mut ip = [0u8; 4];
+    unsafe { core::ptr::copy_nonoverlapping(host_ip, ip.as_mut_ptr(), 4) };
+
+    let quota = quota::Quota::from_limits(total, tcp, udp, icmp);
+    match NAT.lock(|table| table.set_host_quota(ip, Some(quota))) {
+        Some(Ok(())) => 0,
+        _ => -1,
+    }
+}
+
+/// Restore the default mapping quota of an internal host
+#[no_mangle]
+pub extern "C" fn nat_host_quota_clear(host_ip: *const u8) -> i32 {
+    if host_ip.is_null() {
+        return -1;
+    }
+
+    let mut ip = [0u8; 4];
+    unsafe { core::ptr::copy_nonoverlapping(host_ip, ip.as_mut_ptr(), 4) };
+
+    match NAT.lock(|table| table.set_host_quota(ip, None)) {
+        Some(Ok(())) => 0,
+        _ => -1,
+    }
+}
+
+/// Number of sessions of an internal host refused by its quota
+#[no_mangle]
+pub extern "C" fn nat_host_quota_hits(host_ip: *const u8) -> u32 {
+    if host_ip.is_null() {
+        return 0;
+    }
+
+    let mut ip = [0u8; 4];
+    unsafe { core::ptr::copy_nonoverlapping(host_ip, ip.as_mut_ptr(), 4) };
+
+    NAT.lock(|table| table.host_usage(&ip).map_or(0, |usage| usage.hits))
+        .unwrap_or(0)
+}
+
+/// Set the idle timeout (seconds) of sessions to a remote port, 0 removes
+/// the override
+#[no_mangle]
+pub extern "C" fn nat_timeout_override_set(proto: u8, port: u16, timeout_s: u32) -> i32 {
+    let protocol = match entry::Protocol::from_u8(proto) {
+        Some(p) => p,
+        None => return -1,
+    };
+
+    let timeout_ms = (timeout_s != 0).then(|| timeout_s.saturating_mul(1000));
+    let result = NAT.lock(|table| {
+        let mut config = table.config().clone();
+        config
+            .timeouts
+            .set_port_override(protocol, port, timeout_ms)?;
+        table.set_config(config);
+        Ok::<(), table::NatError>(())
+    });
+
+    match result {
+        Some(Ok(())) => 0,
+        _ => -1,
+    }
+}
+
+/// Set the DMZ host, a null pointer clears it
+#[no_mangle]
+pub extern "C" fn nat_dmz_set(host_ip: *const u8) -> i32 {
+    let dmz_host = if host_ip.is_null() {
+        None
+    } else {
+        let mut ip = [0u8; 4];
+        unsafe { core::ptr::copy_nonoverlapping(host_ip, ip.as_mut_ptr(), 4) };
+        Some(ip)
+    };
+
+    let updated = NAT.lock(|table| {
+        let mut config = table.config().clone();
+        config.dmz_host = dmz_host;
+        table.set_config(config);
+    });
+
+    match updated {
+        Some(()) => {
+            log::info!("[NAT] DMZ host: {:?}", dmz_host);
+            0
         }
+        None => -1,
+    }
+}
 
-        let mut config = table::NatConfig::default();
-        core::ptr::copy_nonoverlapping(internal_net, config.internal_network.as_mut_ptr(), 4);
-        core::ptr::copy_nonoverlapping(internal_mask, config.internal_netmask.as_mut_ptr(), 4);
-        core::ptr::copy_nonoverlapping(external_ip, config.external_ip.as_mut_ptr(), 4);
+/// Keep inbound traffic to a port bound by the router out of the DMZ
+#[no_mangle]
+pub extern "C" fn nat_local_port_add(proto: u8, port: u16) -> i32 {
+    let protocol = match entry::Protocol::from_u8(proto) {
+        Some(p) => p,
+        None => return -1,
+    };
 
-        config.internal_iface = internal_iface;
-        config.external_iface = external_iface;
+    let added = NAT.lock(|table| {
+        let mut config = table.config().clone();
+        if config.local_ports.contains(&(protocol, port)) {
+            return true;
+        }
+        let added = config.local_ports.push((protocol, port)).is_ok();
+        table.set_config(config);
+        added
+    });
 
-        log::info!(
-            "[NAT CFG] Internal: {:p}, External: {:p}",
-            internal_iface,
-            external_iface
-        );
+    match added {
+        Some(true) => 0,
+        _ => -1,
+    }
+}
 
+/// Let inbound traffic to a port the router no longer binds reach the DMZ
+#[no_mangle]
+pub extern "C" fn nat_local_port_remove(proto: u8, port: u16) -> i32 {
+    let protocol = match entry::Protocol::from_u8(proto) {
+        Some(p) => p,
+        None => return -1,
+    };
+
+    let removed = NAT.lock(|table| {
+        let mut config = table.config().clone();
+        let before = config.local_ports.len();
+        config
+            .local_ports
+            .retain(|&local| local != (protocol, port));
+        let removed = config.local_ports.len() != before;
         table.set_config(config);
+        removed
+    });
+
+    match removed {
+        Some(true) => 0,
+        _ => -1,
+    }
+}
+
+/// Answer a NAT-PMP/PCP request, returns the response length or -1 to drop
+#[cfg(CONFIG_NET_IPV4_NAT_PMP)]
+#[no_mangle]
+pub extern "C" fn nat_pmp_handle(
+    req: *const u8,
+    req_len: usize,
+    client_ip: *const u8,
+    resp: *mut u8,
+    resp_size: usize,
+) -> i32 {
+    if req.is_null() || client_ip.is_null() || resp.is_null() {
+        return -1;
+    }
+
+    let (req, resp) = unsafe {
+        (
+            core::slice::from_raw_parts(req, req_len),
+            core::slice::from_raw_parts_mut(resp, resp_size),
+        )
+    };
+    let mut ip = [0u8; 4];
+    unsafe { core::ptr::copy_nonoverlapping(client_ip, ip.as_mut_ptr(), 4) };
+
+    match NAT.lock(|table| pmp::handle_request(table, req, &ip, resp)) {
+        Some(Some(len)) => len as i32,
+        _ => -1,
+    }
+}
+
+/// Build an unsolicited NAT-PMP (`pcp` false) or PCP address announcement
+#[cfg(CONFIG_NET_IPV4_NAT_PMP)]
+#[no_mangle]
+pub extern "C" fn nat_pmp_announcement(pcp: bool, buf: *mut u8, size: usize) -> i32 {
+    if buf.is_null() {
+        return -1;
+    }
+
+    let buf = unsafe { core::slice::from_raw_parts_mut(buf, size) };
+    match NAT.lock(|table| pmp::announcement(table, pcp, buf)) {
+        Some(Some(len)) => len as i32,
+        _ => -1,
+    }
+}
+
+/// Answer an SSDP M-SEARCH, returns the response length or -1 to ignore it
+#[cfg(CONFIG_NET_IPV4_NAT_UPNP)]
+#[no_mangle]
+pub extern "C" fn nat_upnp_ssdp(
+    req: *const u8,
+    req_len: usize,
+    host_ip: *const u8,
+    resp: *mut u8,
+    resp_size: usize,
+) -> i32 {
+    if req.is_null() || host_ip.is_null() || resp.is_null() {
+        return -1;
+    }
+
+    let (req, resp) = unsafe {
+        (
+            core::slice::from_raw_parts(req, req_len),
+            core::slice::from_raw_parts_mut(resp, resp_size),
+        )
+    };
+    let mut host = [0u8; 4];
+    unsafe { core::ptr::copy_nonoverlapping(host_ip, host.as_mut_ptr(), 4) };
+
+    match upnp::handle_ssdp(req, &host, resp) {
+        Some(len) => len as i32,
+        None => -1,
+    }
+}
+
+/// Build SSDP NOTIFY ssdp:alive number `index`, -1 once all were built
+#[cfg(CONFIG_NET_IPV4_NAT_UPNP)]
+#[no_mangle]
+pub extern "C" fn nat_upnp_notify(
+    index: usize,
+    host_ip: *const u8,
+    buf: *mut u8,
+    size: usize,
+) -> i32 {
+    if host_ip.is_null() || buf.is_null() {
+        return -1;
+    }
+
+    let buf = unsafe { core::slice::from_raw_parts_mut(buf, size) };
+    let mut host = [0u8; 4];
+    unsafe { core::ptr::copy_nonoverlapping(host_ip, host.as_mut_ptr(), 4) };
+
+    match upnp::notify(index, &host, buf) {
+        Some(len) => len as i32,
+        None => -1,
+    }
+}
+
+/// Answer a UPnP HTTP request received into a buffer of `req_size` bytes
+///
+/// Returns the response length, 0 while the request is incomplete or -1
+/// to close the connection. Requests that cannot fit the buffer are
+/// answered with an error status.
+#[cfg(CONFIG_NET_IPV4_NAT_UPNP)]
+#[no_mangle]
+pub extern "C" fn nat_upnp_http(
+    req: *const u8,
+    req_len: usize,
+    req_size: usize,
+    client_ip: *const u8,
+    host_ip: *const u8,
+    resp: *mut u8,
+    resp_size: usize,
+) -> i32 {
+    if req.is_null() || client_ip.is_null() || host_ip.is_null() || resp.is_null() {
+        return -1;
+    }
+
+    let (req, resp) = unsafe {
+        (
+            core::slice::from_raw_parts(req, req_len),
+            core::slice::from_raw_parts_mut(resp, resp_size),
+        )
+    };
+    let mut client = [0u8; 4];
+    let mut host = [0u8; 4];
+    unsafe {
+        core::ptr::copy_nonoverlapping(client_ip, client.as_mut_ptr(), 4);
+        core::ptr::copy_nonoverlapping(host_ip, host.as_mut_ptr(), 4);
+    }
+
+    let request = match upnp::parse_http(req, req_size) {
+        Some(request) => request,
+        None => return 0,
+    };
+
+    // The response is written once the table is released
+    let outcome = match NAT.lock(|table| upnp::execute(table, &request, &client)) {
+        Some(outcome) => outcome,
+        None => return -1,
+    };
+
+    match upnp::respond_http(&request, outcome, &host, resp) {
+        Some(0) | None => -1,
+        Some(len) => len as i32,
     }
-    0
 }
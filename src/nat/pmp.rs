@@ -0,0 +1,400 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+//! NAT-PMP (RFC 6886) and PCP (RFC 6887) request handling
+//!
+//! The UDP socket on the AP address lives in pmp.c, requests are answered
+//! here against `NatTable` leases.
+
+use super::entry::Protocol;
+use super::table::NatError;
+use super::NatTable;
+
+const MAX_LIFETIME: u32 = zephyr::kconfig::CONFIG_NET_IPV4_NAT_PMP_MAX_LIFETIME as u32;
+
+/// Lifetime reported with PCP error responses (seconds)
+const PCP_ERROR_LIFETIME: u32 = 30;
+
+/// Largest response built here (PCP MAP without options)
+pub const MAX_RESPONSE: usize = 60;
+
+const PMP_VERSION: u8 = 0;
+const PMP_OP_EXTERNAL_ADDRESS: u8 = 0;
+const PMP_OP_MAP_UDP: u8 = 1;
+const PMP_OP_MAP_TCP: u8 = 2;
+const PMP_RESPONSE: u8 = 128;
+
+const PMP_SUCCESS: u16 = 0;
+const PMP_NOT_AUTHORIZED: u16 = 2;
+const PMP_NETWORK_FAILURE: u16 = 3;
+const PMP_OUT_OF_RESOURCES: u16 = 4;
+const PMP_UNSUPPORTED_OPCODE: u16 = 5;
+
+const PCP_VERSION: u8 = 2;
+const PCP_MAX_MESSAGE: usize = 1100;
+const PCP_HEADER_LEN: usize = 24;
+const PCP_MAP_LEN: usize = 36;
+const PCP_OP_ANNOUNCE: u8 = 0;
+const PCP_OP_MAP: u8 = 1;
+const PCP_RESPONSE: u8 = 0x80;
+const PCP_OPTION_PREFER_FAILURE: u8 = 2;
+/// MAP protocol number standing for all protocols
+const PCP_PROTOCOL_ALL: u8 = 0;
+/// Options below this code are mandatory to understand
+const PCP_OPTION_OPTIONAL: u8 = 128;
+
+const PCP_SUCCESS: u8 = 0;
+const PCP_UNSUPP_VERSION: u8 = 1;
+const PCP_NOT_AUTHORIZED: u8 = 2;
+const PCP_MALFORMED_REQUEST: u8 = 3;
+const PCP_UNSUPP_OPCODE: u8 = 4;
+const PCP_UNSUPP_OPTION: u8 = 5;
+const PCP_MALFORMED_OPTION: u8 = 6;
+const PCP_NETWORK_FAILURE: u8 = 7;
+const PCP_NO_RESOURCES: u8 = 8;
+const PCP_UNSUPP_PROTOCOL: u8 = 9;
+const PCP_CANNOT_PROVIDE_EXTERNAL: u8 = 11;
+const PCP_ADDRESS_MISMATCH: u8 = 12;
+
+/// Answer a NAT-PMP or PCP request from `client_ip`
+///
+/// Returns the response length, or `None` when the request is dropped
+/// silently. `resp` must hold at least `MAX_RESPONSE` bytes.
+pub fn handle_request(
+    table: &mut NatTable,
+    req: &[u8],
+    client_ip: &[u8; 4],
+    resp: &mut [u8],
+) -> Option<usize> {
+    if req.len() < 2 || resp.len() < MAX_RESPONSE {
+        return None;
+    }
+
+    match req[0] {
+        PMP_VERSION => handle_pmp(table, req, client_ip, resp),
+        PCP_VERSION => handle_pcp(table, req, client_ip, resp),
+        _ => Some(pcp_header(
+            table,
+            resp,
+            req[1] & !PCP_RESPONSE,
+            PCP_UNSUPP_VERSION,
+            PCP_ERROR_LIFETIME,
+        )),
+    }
+}
+
+/// Unsolicited address announcement sent after the external address
+/// changed, `pcp` selects the PCP ANNOUNCE over the NAT-PMP form
+pub fn announcement(table: &NatTable, pcp: bool, resp: &mut [u8]) -> Option<usize> {
+    if resp.len() < MAX_RESPONSE {
+        return None;
+    }
+
+    if pcp {
+        Some(pcp_header(table, resp, PCP_OP_ANNOUNCE, PCP_SUCCESS, 0))
+    } else {
+        let len = pmp_header(table, resp, PMP_OP_EXTERNAL_ADDRESS, PMP_SUCCESS);
+        resp[len..len + 4].copy_from_slice(&table.config().external_ip);
+        Some(len + 4)
+    }
+}
+
+fn pmp_header(table: &NatTable, resp: &mut [u8], op: u8, result: u16) -> usize {
+    resp[0] = PMP_VERSION;
+    resp[1] = op | PMP_RESPONSE;
+    resp[2..4].copy_from_slice(&result.to_be_bytes());
+    resp[4..8].copy_from_slice(&table.epoch().to_be_bytes());
+    8
+}
+
+fn handle_pmp(
+    table: &mut NatTable,
+    req: &[u8],
+    client_ip: &[u8; 4],
+    resp: &mut [u8],
+) -> Option<usize> {
+    let op = req[1];
+
+    // Responses from other servers are never answered
+    if op & PMP_RESPONSE != 0 {
+        return None;
+    }
+
+    let proto = match op {
+        PMP_OP_EXTERNAL_ADDRESS => {
+            let external_ip = table.config().external_ip;
+            let result = if external_ip == [0; 4] {
+                PMP_NETWORK_FAILURE
+            } else {
+                PMP_SUCCESS
+            };
+            let len = pmp_header(table, resp, op, result);
+            resp[len..len + 4].copy_from_slice(&external_ip);
+            return Some(len + 4);
+        }
+        PMP_OP_MAP_UDP => Protocol::Udp,
+        PMP_OP_MAP_TCP => Protocol::Tcp,
+        _ => return Some(pmp_header(table, resp, op, PMP_UNSUPPORTED_OPCODE)),
+    };
+
+    if req.len() < 12 {
+        return None;
+    }
+
+    let internal_port = u16::from_be_bytes([req[4], req[5]]);
+    let suggested_port = u16::from_be_bytes([req[6], req[7]]);
+    let lifetime = u32::from_be_bytes([req[8], req[9], req[10], req[11]]);
+
+    let (result, external_port, lifetime) = if !table.is_internal_ip(client_ip) {
+        (PMP_NOT_AUTHORIZED, 0, 0)
+    } else if table.config().external_ip == [0; 4] {
+        (PMP_NETWORK_FAILURE, 0, 0)
+    } else if lifetime == 0 {
+        // Internal port 0 deletes every mapping of the client
+        let port = (internal_port != 0).then_some(internal_port);
+        let _ = table.unmap_lease(*client_ip, port, Some(proto), None);
+        (PMP_SUCCESS, 0, 0)
+    } else if internal_port == 0 {
+        (PMP_NOT_AUTHORIZED, 0, 0)
+    } else {
+        let lifetime = lifetime.min(MAX_LIFETIME);
+        match table.map_lease(
+            *client_ip,
+            internal_port,
+            proto,
+            suggested_port,
+            lifetime,
+            [0; 12],
+            false,
+        ) {
+            Ok(port) => (PMP_SUCCESS, port, lifetime),
+            Err(NatError::NotAuthorized) => (PMP_NOT_AUTHORIZED, 0, 0),
+            Err(_) => (PMP_OUT_OF_RESOURCES, 0, 0),
+        }
+    };
+
+    let len = pmp_header(table, resp, op, result);
+    resp[len..len + 2].copy_from_slice(&internal_port.to_be_bytes());
+    resp[len + 2..len + 4].copy_from_slice(&external_port.to_be_bytes());
+    resp[len + 4..len + 8].copy_from_slice(&lifetime.to_be_bytes());
+    Some(len + 8)
+}
+
+fn pcp_header(table: &NatTable, resp: &mut [u8], opcode: u8, result: u8, lifetime: u32) -> usize {
+    resp[..PCP_HEADER_LEN].fill(0);
+    resp[0] = PCP_VERSION;
+    resp[1] = opcode | PCP_RESPONSE;
+    resp[3] = result;
+    resp[4..8].copy_from_slice(&lifetime.to_be_bytes());
+    resp[8..12].copy_from_slice(&table.epoch().to_be_bytes());
+    PCP_HEADER_LEN
+}
+
+/// IPv4 address as carried in PCP (IPv4-mapped IPv6)
+fn ipv4_mapped(ip: &[u8; 4]) -> [u8; 16] {
+    let mut mapped = [0u8; 16];
+    mapped[10] = 0xFF;
+    mapped[11] = 0xFF;
+    mapped[12..].copy_from_slice(ip);
+    mapped
+}
+
+fn handle_pcp(
+    table: &mut NatTable,
+    req: &[u8],
+    client_ip: &[u8; 4],
+    resp: &mut [u8],
+) -> Option<usize> {
+    if req[1] & PCP_RESPONSE != 0 {
+        return None;
+    }
+
+    let opcode = req[1];
+    let error = |table: &NatTable, resp: &mut [u8], result| {
+        Some(pcp_header(table, resp, opcode, result, PCP_ERROR_LIFETIME))
+    };
+
+    if req.len() < PCP_HEADER_LEN || req.len() > PCP_MAX_MESSAGE || !req.len().is_multiple_of(4) {
+        return error(table, resp, PCP_MALFORMED_REQUEST);
+    }
+
+    if req[8..24] != ipv4_mapped(client_ip) {
+        return error(table, resp, PCP_ADDRESS_MISMATCH);
+    }
+
+    if !table.is_internal_ip(client_ip) {
+        return error(table, resp, PCP_NOT_AUTHORIZED);
+    }
+
+    let lifetime = u32::from_be_bytes([req[4], req[5], req[6], req[7]]);
+
+    match opcode {
+        PCP_OP_ANNOUNCE => Some(pcp_header(table, resp, opcode, PCP_SUCCESS, 0)),
+        PCP_OP_MAP => handle_pcp_map(table, req, client_ip, lifetime, resp),
+        _ => error(table, resp, PCP_UNSUPP_OPCODE),
+    }
+}
+
+fn handle_pcp_map(
+    table: &mut NatTable,
+    req: &[u8],
+    client_ip: &[u8; 4],
+    lifetime: u32,
+    resp: &mut [u8],
+) -> Option<usize> {
+    let map_end = PCP_HEADER_LEN + PCP_MAP_LEN;
+    if req.len() < map_end {
+        let len = pcp_header(
+            table,
+            resp,
+            PCP_OP_MAP,
+            PCP_MALFORMED_REQUEST,
+            PCP_ERROR_LIFETIME,
+        );
+        return Some(len);
+    }
+
+    // The response echoes the request's MAP data, updated on success
+    resp[PCP_HEADER_LEN..map_end].copy_from_slice(&req[PCP_HEADER_LEN..map_end]);
+    let map = &req[PCP_HEADER_LEN..map_end];
+
+    let mut nonce = [0u8; 12];
+    nonce.copy_from_slice(&map[..12]);
+    let internal_port = u16::from_be_bytes([map[16], map[17]]);
+    let suggested_port = u16::from_be_bytes([map[18], map[19]]);
+
+    let (result, lifetime) = match pcp_options(&req[map_end..]) {
+        Err(result) => (result, PCP_ERROR_LIFETIME),
+        Ok(prefer_failure) => {
+            match pcp_map(
+                table,
+                client_ip,
+                map[12],
+                internal_port,
+                suggested_port,
+                lifetime,
+                nonce,
+                prefer_failure,
+            ) {
+                Ok((external_port, lifetime)) => {
+                    let ext = PCP_HEADER_LEN + 18;
+                    resp[ext..ext + 2].copy_from_slice(&external_port.to_be_bytes());
+                    resp[ext + 2..ext + 18]
+                        .copy_from_slice(&ipv4_mapped(&table.config().external_ip));
+                    (PCP_SUCCESS, lifetime)
+                }
+                Err(result) => (result, PCP_ERROR_LIFETIME),
+            }
+        }
+    };
+
+    pcp_header(table, resp, PCP_OP_MAP, result, lifetime);
+    Some(map_end)
+}
+
+/// Walk the options of a MAP request, returns whether PREFER_FAILURE is set
+fn pcp_options(mut options: &[u8]) -> Result<bool, u8> {
+    let mut prefer_failure = false;
+
+    while !options.is_empty() {
+        if options.len() < 4 {
+            return Err(PCP_MALFORMED_OPTION);
+        }
+
+        let code = options[0];
+        let len = u16::from_be_bytes([options[2], options[3]]) as usize;
+        let padded = (len + 3) & !3;
+        if options.len() < 4 + padded {
+            return Err(PCP_MALFORMED_OPTION);
+        }
+
+        match code {
+            PCP_OPTION_PREFER_FAILURE => prefer_failure = true,
+            c if c < PCP_OPTION_OPTIONAL => return Err(PCP_UNSUPP_OPTION),
+            _ => {}
+        }
+
+        options = &options[4 + padded..];
+    }
+
+    Ok(prefer_failure)
+}
+
+/// Delete the mappings of a MAP request with lifetime 0
+///
+/// Protocol 0 deletes the client's mappings of every protocol, whatever
+/// the internal port (RFC 6887 section 11.1). Mappings created with
+/// another nonce belong to another PCP client (section 15).
+fn pcp_delete(
+    table: &mut NatTable,
+    client_ip: &[u8; 4],
+    protocol: u8,
+    internal_port: u16,
+    nonce: [u8; 12],
+) -> Result<(), u8> {
+    let (proto, port) = match protocol {
+        PCP_PROTOCOL_ALL => (None, None),
+        _ => match Protocol::from_u8(protocol) {
+            Some(p @ (Protocol::Tcp | Protocol::Udp)) => {
+                (Some(p), (internal_port != 0).then_some(internal_port))
+            }
+            _ => return Err(PCP_UNSUPP_PROTOCOL),
+        },
+    };
+
+    match table.unmap_lease(*client_ip, port, proto, Some(nonce)) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(PCP_NOT_AUTHORIZED),
+    }
+}
+
+/// Apply a MAP request, returns the external port and granted lifetime
+#[allow(clippy::too_many_arguments)]
+fn pcp_map(
+    table: &mut NatTable,
+    client_ip: &[u8; 4],
+    protocol: u8,
+    internal_port: u16,
+    suggested_port: u16,
+    lifetime: u32,
+    nonce: [u8; 12],
+    prefer_failure: bool,
+) -> Result<(u16, u32), u8> {
+    if lifetime == 0 {
+        return pcp_delete(table, client_ip, protocol, internal_port, nonce)
+            .map(|()| (suggested_port, 0));
+    }
+
+    let proto = match Protocol::from_u8(protocol) {
+        Some(p @ (Protocol::Tcp | Protocol::Udp)) => p,
+        _ => return Err(PCP_UNSUPP_PROTOCOL),
+    };
+
+    if table.config().external_ip == [0; 4] {
+        return Err(PCP_NETWORK_FAILURE);
+    }
+
+    if internal_port == 0 {
+        return Err(PCP_MALFORMED_REQUEST);
+    }
+
+    let lifetime = lifetime.min(MAX_LIFETIME);
+    let external_port = table
+        .map_lease(
+            *client_ip,
+            internal_port,
+            proto,
+            suggested_port,
+            lifetime,
+            nonce,
+            prefer_failure,
+        )
+        .map_err(|e| match e {
+            NatError::NotAuthorized => PCP_NOT_AUTHORIZED,
+            NatError::RuleConflict => PCP_CANNOT_PROVIDE_EXTERNAL,
+            _ => PCP_NO_RESOURCES,
+        })?;
+
+    Ok((external_port, lifetime))
+}
@@ -0,0 +1,680 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+//! UPnP IGD: SSDP discovery and the WANIPConnection SOAP control endpoint
+//!
+//! The SSDP and HTTP sockets on the AP interface live in upnp.c, messages
+//! are parsed and answered here. Port mappings are `NatTable` leases, the
+//! same ones NAT-PMP/PCP hand out.
+
+use super::entry::Protocol;
+use super::forward::PortForward;
+use super::table::NatError;
+use super::NatTable;
+use core::fmt::{self, Write};
+
+const HTTP_PORT: u16 = zephyr::kconfig::CONFIG_NET_IPV4_NAT_UPNP_PORT as u16;
+const MAX_LEASE: u32 = zephyr::kconfig::CONFIG_NET_IPV4_NAT_UPNP_MAX_LEASE as u32;
+
+const UUID: &str = "uuid:5a657068-7972-4e41-5452-6f7574657201";
+const UUID_WAN: &str = "uuid:5a657068-7972-4e41-5452-6f7574657202";
+const UUID_WAN_CONN: &str = "uuid:5a657068-7972-4e41-5452-6f7574657203";
+
+const ROOT_DEVICE: &str = "upnp:rootdevice";
+/// Versioned types, followed by "1" or "2"
+const IGD: &str = "urn:schemas-upnp-org:device:InternetGatewayDevice:";
+const WAN_IP_CONNECTION: &str = "urn:schemas-upnp-org:service:WANIPConnection:";
+
+const SERVER: &str = "Zephyr/1.0 UPnP/1.1 NATRouter/1.0";
+const DESC_URL: &str = "/rootDesc.xml";
+const SCPD_URL: &str = "/WANIPCn.xml";
+const CONTROL_URL: &str = "/ctl/IPConn";
+const EVENT_URL: &str = "/evt/IPConn";
+const MAX_AGE: u32 = 1800;
+
+/// Notification types advertised with NOTIFY ssdp:alive
+const NOTIFY_TYPES: [(&str, &str); 4] = [
+    (ROOT_DEVICE, ""),
+    (UUID, ""),
+    (IGD, "1"),
+    (WAN_IP_CONNECTION, "1"),
+];
+
+/// UPnP control error codes
+const INVALID_ACTION: u16 = 401;
+const INVALID_ARGS: u16 = 402;
+const ACTION_FAILED: u16 = 501;
+const NOT_AUTHORIZED: u16 = 606;
+const ARRAY_INDEX_INVALID: u16 = 713;
+const NO_SUCH_ENTRY: u16 = 714;
+const CONFLICT_IN_MAPPING: u16 = 718;
+const WILDCARD_EXTERNAL_PORT: u16 = 716;
+const REMOTE_HOST_WILDCARD_ONLY: u16 = 726;
+
+/// `(name, output, related state variable)` of each action argument
+type Argument = (&'static str, bool, &'static str);
+
+const ACTIONS: [(&str, &[Argument]); 4] = [
+    (
+        "AddPortMapping",
+        &[
+            ("NewRemoteHost", false, "RemoteHost"),
+            ("NewExternalPort", false, "ExternalPort"),
+            ("NewProtocol", false, "PortMappingProtocol"),
+            ("NewInternalPort", false, "InternalPort"),
+            ("NewInternalClient", false, "InternalClient"),
+            ("NewEnabled", false, "PortMappingEnabled"),
+            ("NewPortMappingDescription", false, "PortMappingDescription"),
+            ("NewLeaseDuration", false, "PortMappingLeaseDuration"),
+        ],
+    ),
+    (
+        "DeletePortMapping",
+        &[
+            ("NewRemoteHost", false, "RemoteHost"),
+            ("NewExternalPort", false, "ExternalPort"),
+            ("NewProtocol", false, "PortMappingProtocol"),
+        ],
+    ),
+    (
+        "GetExternalIPAddress",
+        &[("NewExternalIPAddress", true, "ExternalIPAddress")],
+    ),
+    (
+        "GetGenericPortMappingEntry",
+        &[
+            ("NewPortMappingIndex", false, "PortMappingNumberOfEntries"),
+            ("NewRemoteHost", true, "RemoteHost"),
+            ("NewExternalPort", true, "ExternalPort"),
+            ("NewProtocol", true, "PortMappingProtocol"),
+            ("NewInternalPort", true, "InternalPort"),
+            ("NewInternalClient", true, "InternalClient"),
+            ("NewEnabled", true, "PortMappingEnabled"),
+            ("NewPortMappingDescription", true, "PortMappingDescription"),
+            ("NewLeaseDuration", true, "PortMappingLeaseDuration"),
+        ],
+    ),
+];
+
+/// `(name, data type)` of the service state variables
+const STATE_VARIABLES: [(&str, &str); 10] = [
+    ("RemoteHost", "string"),
+    ("ExternalPort", "ui2"),
+    ("PortMappingProtocol", "string"),
+    ("InternalPort", "ui2"),
+    ("InternalClient", "string"),
+    ("PortMappingEnabled", "boolean"),
+    ("PortMappingDescription", "string"),
+    ("PortMappingLeaseDuration", "ui4"),
+    ("ExternalIPAddress", "string"),
+    ("PortMappingNumberOfEntries", "ui2"),
+];
+
+/// Bounded `fmt::Write` sink over a byte buffer
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+}
+
+impl Write for Writer<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.buf.len() {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+struct Ip<'a>(&'a [u8; 4]);
+
+impl fmt::Display for Ip<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.0[0], self.0[1], self.0[2], self.0[3])
+    }
+}
+
+fn parse_ip(s: &str) -> Option<[u8; 4]> {
+    let mut ip = [0u8; 4];
+    let mut parts = s.split('.');
+    for octet in ip.iter_mut() {
+        *octet = parts.next()?.parse().ok()?;
+    }
+    parts.next().is_none().then_some(ip)
+}
+
+/// Value of header `name` in an HTTP-style message
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.split("\r\n")
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+/// Text of the `<name>` element of a SOAP request body
+fn argument<'a>(body: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = body;
+    while let Some(pos) = rest.find('<') {
+        rest = &rest[pos + 1..];
+        let tail = match rest.strip_prefix(name) {
+            Some(tail) => tail,
+            None => continue,
+        };
+        if let Some(value) = tail.strip_prefix('>') {
+            return value.find("</").map(|end| value[..end].trim());
+        }
+        if tail.trim_start().starts_with("/>") {
+            return Some("");
+        }
+    }
+    None
+}
+
+/// Check `st` is `prefix` followed by a supported version
+fn is_versioned(st: &str, prefix: &str) -> bool {
+    matches!(st.strip_prefix(prefix), Some("1" | "2"))
+}
+
+/// Headers shared by M-SEARCH responses and NOTIFY, the type is split as
+/// `prefix` and `version` so versioned types can be built without a copy
+fn write_ssdp_common(
+    w: &mut Writer,
+    host_ip: &[u8; 4],
+    prefix: &str,
+    version: &str,
+) -> fmt::Result {
+    write!(
+        w,
+        "CACHE-CONTROL: max-age={}\r\n\
+         LOCATION: http://{}:{}{}\r\n\
+         SERVER: {}\r\n",
+        MAX_AGE,
+        Ip(host_ip),
+        HTTP_PORT,
+        DESC_URL,
+        SERVER
+    )?;
+    if prefix == UUID {
+        write!(w, "USN: {}\r\n", UUID)
+    } else {
+        write!(w, "USN: {}::{}{}\r\n", UUID, prefix, version)
+    }
+}
+
+/// Answer an SSDP M-SEARCH, returns the unicast response length
+pub fn handle_ssdp(req: &[u8], host_ip: &[u8; 4], resp: &mut [u8]) -> Option<usize> {
+    let req = core::str::from_utf8(req).ok()?;
+
+    if !req.starts_with("M-SEARCH * HTTP/1.1\r\n")
+        || header(req, "MAN")?.trim_matches('"') != "ssdp:discover"
+    {
+        return None;
+    }
+
+    let st = match header(req, "ST")? {
+        "ssdp:all" => ROOT_DEVICE,
+        st if st == ROOT_DEVICE || st == UUID => st,
+        st if is_versioned(st, IGD) || is_versioned(st, WAN_IP_CONNECTION) => st,
+        _ => return None,
+    };
+
+    let mut w = Writer::new(resp);
+    w.write_str("HTTP/1.1 200 OK\r\nEXT:\r\n").ok()?;
+    write_ssdp_common(&mut w, host_ip, st, "").ok()?;
+    write!(w, "ST: {}\r\n\r\n", st).ok()?;
+    Some(w.len)
+}
+
+/// NOTIFY ssdp:alive number `index`, `None` once all were built
+pub fn notify(index: usize, host_ip: &[u8; 4], resp: &mut [u8]) -> Option<usize> {
+    let (prefix, version) = NOTIFY_TYPES.get(index)?;
+
+    let mut w = Writer::new(resp);
+    w.write_str("NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n")
+        .ok()?;
+    write_ssdp_common(&mut w, host_ip, prefix, version).ok()?;
+    write!(w, "NT: {}{}\r\nNTS: ssdp:alive\r\n\r\n", prefix, version).ok()?;
+    Some(w.len)
+}
+
+/// HTTP request on the description/control port
+///
+/// Requests are parsed before the NAT table is locked and answered after
+/// it is released, only `execute` runs with the lock held.
+pub enum Request<'a> {
+    /// Malformed or oversized, refused whoever sent it
+    Refused(&'static str),
+    /// Answered with an empty body
+    Status(&'static str),
+    Description,
+    Scpd,
+    /// WANIPConnection SOAP action
+    Control {
+        service: &'a str,
+        action: &'a str,
+        body: &'a str,
+    },
+    /// SOAP request for another service or without a SOAPACTION
+    InvalidAction,
+}
+
+/// Table side of a request
+pub enum Outcome {
+    /// The client is not on the LAN
+    Forbidden,
+    Reply(Reply),
+    /// UPnP error code of a failed action
+    Error(u16),
+}
+
+/// Parse an HTTP request received into a buffer of `capacity` bytes,
+/// `None` while it is incomplete
+pub fn parse_http(req: &[u8], capacity: usize) -> Option<Request<'_>> {
+    let head_end = match req.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(end) => end,
+        None if req.len() >= capacity => {
+            return Some(Request::Refused("431 Request Header Fields Too Large"))
+        }
+        None => return None,
+    };
+
+    let head = match core::str::from_utf8(&req[..head_end + 2]) {
+        Ok(head) => head,
+        Err(_) => return Some(Request::Refused("400 Bad Request")),
+    };
+
+    let body_len = match header(head, "CONTENT-LENGTH").map(str::parse::<usize>) {
+        Some(Ok(len)) => len,
+        Some(Err(_)) => return Some(Request::Refused("400 Bad Request")),
+        None => 0,
+    };
+    let body_end = match (head_end + 4).checked_add(body_len) {
+        Some(end) if end <= capacity => end,
+        _ => return Some(Request::Refused("413 Payload Too Large")),
+    };
+    let body = req.get(head_end + 4..body_end)?;
+
+    let mut request_line = head.split(' ');
+    let request = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some(DESC_URL)) => Request::Description,
+        (Some("GET"), Some(SCPD_URL)) => Request::Scpd,
+        (Some("POST"), Some(CONTROL_URL)) => {
+            // SOAPACTION: "urn:schemas-upnp-org:service:WANIPConnection:1#AddPortMapping"
+            match header(head, "SOAPACTION")
+                .map(|v| v.trim_matches('"'))
+                .and_then(|v| v.split_once('#'))
+            {
+                Some((service, action)) if is_versioned(service, WAN_IP_CONNECTION) => {
+                    Request::Control {
+                        service,
+                        action,
+                        body: core::str::from_utf8(body).unwrap_or(""),
+                    }
+                }
+                _ => Request::InvalidAction,
+            }
+        }
+        (Some("GET" | "POST" | "SUBSCRIBE"), Some(_)) => Request::Status("404 Not Found"),
+        _ => Request::Status("501 Not Implemented"),
+    };
+
+    Some(request)
+}
+
+/// Check the client and run the action of `request` on the table
+pub fn execute(table: &mut NatTable, request: &Request, client_ip: &[u8; 4]) -> Outcome {
+    if !table.is_internal_ip(client_ip) {
+        return Outcome::Forbidden;
+    }
+
+    let (action, body) = match request {
+        Request::Control { action, body, .. } => (*action, *body),
+        _ => return Outcome::Reply(Reply::Empty),
+    };
+
+    let result = match action {
+        "AddPortMapping" => add_port_mapping(table, body, client_ip),
+        "DeletePortMapping" => delete_port_mapping(table, body, client_ip),
+        "GetExternalIPAddress" => Ok(Reply::ExternalIp(table.config().external_ip)),
+        "GetGenericPortMappingEntry" => get_generic_entry(table, body),
+        _ => Err(INVALID_ACTION),
+    };
+
+    match result {
+        Ok(reply) => Outcome::Reply(reply),
+        Err(code) => Outcome::Error(code),
+    }
+}
+
+/// Write the response to `request`, returns its length
+pub fn respond_http(
+    request: &Request,
+    outcome: Outcome,
+    host_ip: &[u8; 4],
+    resp: &mut [u8],
+) -> Option<usize> {
+    // Malformed requests are refused before the client is looked at
+    if let Request::Refused(status) = request {
+        return respond(resp, status, |_| Ok(()));
+    }
+
+    let reply = match outcome {
+        Outcome::Forbidden => return respond(resp, "403 Forbidden", |_| Ok(())),
+        Outcome::Error(code) => return soap_error(resp, code),
+        Outcome::Reply(reply) => reply,
+    };
+
+    match request {
+        Request::Refused(status) | Request::Status(status) => respond(resp, status, |_| Ok(())),
+        Request::Description => respond(resp, "200 OK", |w| description(w, host_ip)),
+        Request::Scpd => respond(resp, "200 OK", scpd),
+        Request::Control {
+            service, action, ..
+        } => control_response(service, action, reply, resp),
+        Request::InvalidAction => soap_error(resp, INVALID_ACTION),
+    }
+}
+
+/// Write an HTTP response, `body` fills in the XML payload
+fn respond(
+    resp: &mut [u8],
+    status: &str,
+    body: impl FnOnce(&mut Writer) -> fmt::Result,
+) -> Option<usize> {
+    let mut w = Writer::new(resp);
+    write!(
+        w,
+        "HTTP/1.1 {}\r\n\
+         CONTENT-TYPE: text/xml; charset=\"utf-8\"\r\n\
+         SERVER: {}\r\n\
+         CONNECTION: close\r\n\
+         CONTENT-LENGTH: ",
+        status, SERVER
+    )
+    .ok()?;
+
+    // Fixed width length, patched once the body is written
+    let length_pos = w.len;
+    w.write_str("00000\r\n\r\n").ok()?;
+    let body_start = w.len;
+    body(&mut w).ok()?;
+
+    let (len, body_len) = (w.len, w.len - body_start);
+    if body_len > 99999 {
+        return None;
+    }
+    let mut digits = body_len;
+    for b in resp[length_pos..length_pos + 5].iter_mut().rev() {
+        *b = b'0' + (digits % 10) as u8;
+        digits /= 10;
+    }
+    Some(len)
+}
+
+fn description(w: &mut Writer, host_ip: &[u8; 4]) -> fmt::Result {
+    write!(
+        w,
+        "<?xml version=\"1.0\"?>\r\n\
+         <root xmlns=\"urn:schemas-upnp-org:device-1-0\">\
+         <specVersion><major>1</major><minor>0</minor></specVersion>\
+         <URLBase>http://{}:{}/</URLBase>\
+         <device><deviceType>{}1</deviceType>\
+         <friendlyName>Zephyr NAT Router</friendlyName>\
+         <manufacturer>Zephyr</manufacturer><modelName>NAT Router</modelName>\
+         <UDN>{}</UDN><deviceList>\
+         <device><deviceType>urn:schemas-upnp-org:device:WANDevice:1</deviceType>\
+         <friendlyName>WANDevice</friendlyName>\
+         <manufacturer>Zephyr</manufacturer><modelName>NAT Router</modelName>\
+         <UDN>{}</UDN><deviceList>\
+         <device><deviceType>urn:schemas-upnp-org:device:WANConnectionDevice:1</deviceType>\
+         <friendlyName>WANConnectionDevice</friendlyName>\
+         <manufacturer>Zephyr</manufacturer><modelName>NAT Router</modelName>\
+         <UDN>{}</UDN><serviceList>\
+         <service><serviceType>{}1</serviceType>\
+         <serviceId>urn:upnp-org:serviceId:WANIPConn1</serviceId>\
+         <SCPDURL>{}</SCPDURL><controlURL>{}</controlURL><eventSubURL>{}</eventSubURL>\
+         </service></serviceList></device></deviceList></device></deviceList>\
+         </device></root>\r\n",
+        Ip(host_ip),
+        HTTP_PORT,
+        IGD,
+        UUID,
+        UUID_WAN,
+        UUID_WAN_CONN,
+        WAN_IP_CONNECTION,
+        SCPD_URL,
+        CONTROL_URL,
+        EVENT_URL
+    )
+}
+
+/// WANIPConnection service description, generated from `ACTIONS`
+fn scpd(w: &mut Writer) -> fmt::Result {
+    w.write_str(
+        "<?xml version=\"1.0\"?>\r\n\
+         <scpd xmlns=\"urn:schemas-upnp-org:service-1-0\">\
+         <specVersion><major>1</major><minor>0</minor></specVersion><actionList>",
+    )?;
+
+    for (name, arguments) in ACTIONS.iter() {
+        write!(w, "<action><name>{}</name><argumentList>", name)?;
+        for (arg, output, variable) in arguments.iter() {
+            write!(
+                w,
+                "<argument><name>{}</name><direction>{}</direction>\
+                 <relatedStateVariable>{}</relatedStateVariable></argument>",
+                arg,
+                if *output { "out" } else { "in" },
+                variable
+            )?;
+        }
+        w.write_str("</argumentList></action>")?;
+    }
+
+    w.write_str("</actionList><serviceStateTable>")?;
+    for (name, data_type) in STATE_VARIABLES.iter() {
+        write!(
+            w,
+            "<stateVariable sendEvents=\"no\"><name>{}</name><dataType>{}</dataType>",
+            name, data_type
+        )?;
+        if *name == "PortMappingProtocol" {
+            w.write_str(
+                "<allowedValueList><allowedValue>TCP</allowedValue>\
+                 <allowedValue>UDP</allowedValue></allowedValueList>",
+            )?;
+        }
+        w.write_str("</stateVariable>")?;
+    }
+    w.write_str("</serviceStateTable></scpd>\r\n")
+}
+
+/// Successful action results
+pub enum Reply {
+    Empty,
+    ExternalIp([u8; 4]),
+    Entry(PortForward, u32),
+}
+
+fn control_response(service: &str, action: &str, reply: Reply, resp: &mut [u8]) -> Option<usize> {
+    respond(resp, "200 OK", |w| {
+        write!(
+            w,
+            "<?xml version=\"1.0\"?>\r\n\
+             <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+             s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\"><s:Body>\
+             <u:{}Response xmlns:u=\"{}\">",
+            action, service
+        )?;
+        match reply {
+            Reply::Empty => {}
+            Reply::ExternalIp(ip) => write!(
+                w,
+                "<NewExternalIPAddress>{}</NewExternalIPAddress>",
+                Ip(&ip)
+            )?,
+            Reply::Entry(rule, lease) => write!(
+                w,
+                "<NewRemoteHost></NewRemoteHost>\
+                 <NewExternalPort>{}</NewExternalPort>\
+                 <NewProtocol>{}</NewProtocol>\
+                 <NewInternalPort>{}</NewInternalPort>\
+                 <NewInternalClient>{}</NewInternalClient>\
+                 <NewEnabled>1</NewEnabled>\
+                 <NewPortMappingDescription></NewPortMappingDescription>\
+                 <NewLeaseDuration>{}</NewLeaseDuration>",
+                rule.external_start,
+                if rule.protocol == Protocol::Tcp {
+                    "TCP"
+                } else {
+                    "UDP"
+                },
+                rule.internal_port,
+                Ip(&rule.internal_ip),
+                lease
+            )?,
+        }
+        write!(w, "</u:{}Response></s:Body></s:Envelope>\r\n", action)
+    })
+}
+
+fn soap_error(resp: &mut [u8], code: u16) -> Option<usize> {
+    let description = match code {
+        INVALID_ACTION => "Invalid Action",
+        INVALID_ARGS => "Invalid Args",
+        NOT_AUTHORIZED => "Action not authorized",
+        ARRAY_INDEX_INVALID => "SpecifiedArrayIndexInvalid",
+        NO_SUCH_ENTRY => "NoSuchEntryInArray",
+        WILDCARD_EXTERNAL_PORT => "WildCardNotPermittedInExtPort",
+        CONFLICT_IN_MAPPING => "ConflictInMappingEntry",
+        REMOTE_HOST_WILDCARD_ONLY => "RemoteHostOnlySupportsWildcard",
+        _ => "Action Failed",
+    };
+
+    respond(resp, "500 Internal Server Error", |w| {
+        write!(
+            w,
+            "<?xml version=\"1.0\"?>\r\n\
+             <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+             s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\"><s:Body>\
+             <s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring>\
+             <detail><UPnPError xmlns=\"urn:schemas-upnp-org:control-1-0\">\
+             <errorCode>{}</errorCode><errorDescription>{}</errorDescription>\
+             </UPnPError></detail></s:Fault></s:Body></s:Envelope>\r\n",
+            code, description
+        )
+    })
+}
+
+fn parse_protocol(body: &str) -> Result<Protocol, u16> {
+    match argument(body, "NewProtocol") {
+        Some("TCP") => Ok(Protocol::Tcp),
+        Some("UDP") => Ok(Protocol::Udp),
+        _ => Err(INVALID_ARGS),
+    }
+}
+
+/// Remote host and external port shared by Add/DeletePortMapping
+fn parse_external(body: &str) -> Result<u16, u16> {
+    if !argument(body, "NewRemoteHost").unwrap_or("").is_empty() {
+        return Err(REMOTE_HOST_WILDCARD_ONLY);
+    }
+
+    match argument(body, "NewExternalPort").map(str::parse::<u16>) {
+        Some(Ok(0)) => Err(WILDCARD_EXTERNAL_PORT),
+        Some(Ok(port)) => Ok(port),
+        _ => Err(INVALID_ARGS),
+    }
+}
+
+fn add_port_mapping(table: &mut NatTable, body: &str, client_ip: &[u8; 4]) -> Result<Reply, u16> {
+    let external_port = parse_external(body)?;
+    let proto = parse_protocol(body)?;
+
+    let internal_port = match argument(body, "NewInternalPort").map(str::parse::<u16>) {
+        Some(Ok(port)) if port != 0 => port,
+        _ => return Err(INVALID_ARGS),
+    };
+
+    // Clients may only map ports to themselves
+    let internal_ip = argument(body, "NewInternalClient")
+        .and_then(parse_ip)
+        .ok_or(INVALID_ARGS)?;
+    if internal_ip != *client_ip {
+        return Err(NOT_AUTHORIZED);
+    }
+
+    // Disabled mappings are not kept
+    match argument(body, "NewEnabled") {
+        Some("1" | "true" | "yes") | None => {}
+        _ => return Err(INVALID_ARGS),
+    }
+
+    // A duration of 0 asks for a permanent mapping, capped like any other
+    let lease = match argument(body, "NewLeaseDuration").map(str::parse::<u32>) {
+        Some(Ok(0)) | None => MAX_LEASE,
+        Some(Ok(lease)) => lease.min(MAX_LEASE),
+        Some(Err(_)) => return Err(INVALID_ARGS),
+    };
+
+    // The client's lease of the internal port may only be renewed on its
+    // own external port
+    if let Some(rule) = table.lease_of(internal_ip, internal_port, proto) {
+        if rule.external_start != external_port {
+            return Err(CONFLICT_IN_MAPPING);
+        }
+    }
+
+    match table.map_lease(
+        internal_ip,
+        internal_port,
+        proto,
+        external_port,
+        lease,
+        [0; 12],
+        true,
+    ) {
+        Ok(_) => Ok(Reply::Empty),
+        Err(NatError::RuleConflict) => Err(CONFLICT_IN_MAPPING),
+        Err(NatError::NotAuthorized) => Err(NOT_AUTHORIZED),
+        Err(_) => Err(ACTION_FAILED),
+    }
+}
+
+fn delete_port_mapping(
+    table: &mut NatTable,
+    body: &str,
+    client_ip: &[u8; 4],
+) -> Result<Reply, u16> {
+    let external_port = parse_external(body)?;
+    let proto = parse_protocol(body)?;
+
+    match table.unmap_lease_external(*client_ip, external_port, proto) {
+        Ok(()) => Ok(Reply::Empty),
+        Err(NatError::NotAuthorized) => Err(NOT_AUTHORIZED),
+        Err(_) => Err(NO_SUCH_ENTRY),
+    }
+}
+
+fn get_generic_entry(table: &NatTable, body: &str) -> Result<Reply, u16> {
+    let index = match argument(body, "NewPortMappingIndex").map(str::parse::<usize>) {
+        Some(Ok(index)) => index,
+        _ => return Err(INVALID_ARGS),
+    };
+
+    table
+        .lease_at(index)
+        .map(|(rule, lease)| Reply::Entry(rule, lease))
+        .ok_or(ARRAY_INDEX_INVALID)
+}
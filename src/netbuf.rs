@@ -0,0 +1,112 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+use crate::ffi::NetBuf;
+
+/// Position in the data of a `NetBuf` fragment chain
+///
+/// Reads and writes continue across fragment boundaries and never go past
+/// the `len` of a buffer. A buffer with no data counts as empty.
+pub struct NetBufCursor {
+    head: *mut NetBuf,
+    buf: *mut NetBuf,
+    /// Offset inside `buf`
+    pos: usize,
+}
+
+impl NetBufCursor {
+    /// Cursor at the start of the chain
+    ///
+    /// # Safety
+    ///
+    /// `head` is null or the first buffer of a null terminated chain whose
+    /// `data` points to at least `len` bytes. The chain must not be
+    /// accessed through other pointers while the cursor is in use.
+    pub unsafe fn new(head: *mut NetBuf) -> Self {
+        Self {
+            head,
+            buf: head,
+            pos: 0,
+        }
+    }
+
+    /// Bytes of the current buffer from the cursor on
+    fn remaining(&mut self) -> &mut [u8] {
+        unsafe {
+            if self.buf.is_null() || (*self.buf).data.is_null() {
+                return &mut [];
+            }
+            let len = (*self.buf).len as usize;
+            let data = core::slice::from_raw_parts_mut((*self.buf).data, len);
+            data.get_mut(self.pos..).unwrap_or(&mut [])
+        }
+    }
+
+    /// Move to the next buffer once the current one is used up
+    fn next_buf(&mut self) {
+        while !self.buf.is_null() && self.remaining().is_empty() {
+            self.buf = unsafe { (*self.buf).frags };
+            self.pos = 0;
+        }
+    }
+
+    /// Move to `offset` bytes from the start of the chain, `false` when
+    /// the chain is shorter
+    pub fn seek(&mut self, offset: usize) -> bool {
+        self.buf = self.head;
+        self.pos = 0;
+        self.skip(offset)
+    }
+
+    /// Move `count` bytes forward, `false` when the chain is shorter
+    pub fn skip(&mut self, mut count: usize) -> bool {
+        while count > 0 {
+            self.next_buf();
+            let avail = self.remaining().len();
+            if avail == 0 {
+                return false;
+            }
+            let n = avail.min(count);
+            self.pos += n;
+            count -= n;
+        }
+        true
+    }
+
+    /// Copy bytes from the cursor into `out`, returning how many were
+    /// available
+    pub fn read(&mut self, out: &mut [u8]) -> usize {
+        let mut done = 0;
+        while done < out.len() {
+            self.next_buf();
+            let src = self.remaining();
+            let n = src.len().min(out.len() - done);
+            if n == 0 {
+                break;
+            }
+            out[done..done + n].copy_from_slice(&src[..n]);
+            self.pos += n;
+            done += n;
+        }
+        done
+    }
+
+    /// Copy `data` to the cursor, returning how many bytes fit in the
+    /// chain
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        let mut done = 0;
+        while done < data.len() {
+            self.next_buf();
+            let dst = self.remaining();
+            let n = dst.len().min(data.len() - done);
+            if n == 0 {
+                break;
+            }
+            dst[..n].copy_from_slice(&data[done..done + n]);
+            self.pos += n;
+            done += n;
+        }
+        done
+    }
+}
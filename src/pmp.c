@@ -0,0 +1,156 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+#include <zephyr/kernel.h>
+#include <zephyr/logging/log.h>
+#include <zephyr/net/socket.h>
+#include <string.h>
+#include <errno.h>
+
+LOG_MODULE_DECLARE(esp32_wifi, LOG_LEVEL_DBG);
+
+#if defined(CONFIG_NET_IPV4_NAT_PMP)
+
+#define NAT_PMP_PORT 5351
+#define NAT_PMP_CLIENT_PORT 5350
+#define NAT_PMP_MAX_REQUEST 1100
+#define NAT_PMP_MAX_RESPONSE 60
+#define NAT_PMP_ANNOUNCE_COUNT 10
+#define NAT_PMP_STACK_SIZE 2048
+#define NAT_PMP_PRIORITY 7
+
+extern int nat_pmp_handle(const uint8_t *, size_t, const uint8_t *, uint8_t *, size_t);
+extern int nat_pmp_announcement(bool, uint8_t *, size_t);
+
+static int pmp_sock = -1;
+static uint8_t request[NAT_PMP_MAX_REQUEST];
+static uint8_t response[NAT_PMP_MAX_RESPONSE];
+
+static void pmp_announce_handler(struct k_work *work);
+static K_WORK_DELAYABLE_DEFINE(announce_work, pmp_announce_handler);
+static int announce_left;
+static uint32_t announce_delay_ms;
+
+K_THREAD_STACK_DEFINE(pmp_stack, NAT_PMP_STACK_SIZE);
+static struct k_thread pmp_thread;
+
+static void pmp_send_announce(bool pcp)
+{
+    uint8_t buf[NAT_PMP_MAX_RESPONSE];
+    struct sockaddr_in dst = {
+        .sin_family = AF_INET,
+        .sin_port = htons(NAT_PMP_CLIENT_PORT),
+    };
+
+    int len = nat_pmp_announcement(pcp, buf, sizeof(buf));
+    if(len < 0)
+    {
+        return;
+    }
+
+    zsock_inet_pton(AF_INET, "224.0.0.1", &dst.sin_addr);
+    zsock_sendto(pmp_sock, buf, len, 0, (struct sockaddr *)&dst, sizeof(dst));
+}
+
+/* RFC 6886 3.2.1: ten announcements, starting at 250 ms and doubling */
+static void pmp_announce_handler(struct k_work *work)
+{
+    ARG_UNUSED(work);
+
+    if(pmp_sock < 0 || announce_left <= 0)
+    {
+        return;
+    }
+
+    pmp_send_announce(false);
+    pmp_send_announce(true);
+
+    if(--announce_left > 0)
+    {
+        announce_delay_ms *= 2;
+        k_work_reschedule(&announce_work, K_MSEC(announce_delay_ms));
+    }
+}
+
+void nat_pmp_announce(void)
+{
+    announce_left = NAT_PMP_ANNOUNCE_COUNT;
+    announce_delay_ms = 250;
+    k_work_reschedule(&announce_work, K_MSEC(announce_delay_ms));
+}
+
+static void pmp_thread_fn(void *p1, void *p2, void *p3)
+{
+    ARG_UNUSED(p1);
+    ARG_UNUSED(p2);
+    ARG_UNUSED(p3);
+
+    while(1)
+    {
+        struct sockaddr_in client;
+        socklen_t client_len = sizeof(client);
+
+        int len = zsock_recvfrom(pmp_sock, request, sizeof(request), 0,
+                                 (struct sockaddr *)&client, &client_len);
+        if(len < 0)
+        {
+            LOG_ERR("NAT-PMP: recvfrom failed (%d)", errno);
+            k_sleep(K_MSEC(100));
+            continue;
+        }
+
+        int resp_len = nat_pmp_handle(request, len, (const uint8_t *)&client.sin_addr,
+                                      response, sizeof(response));
+        if(resp_len > 0)
+        {
+            zsock_sendto(pmp_sock, response, resp_len, 0,
+                         (struct sockaddr *)&client, client_len);
+        }
+    }
+}
+
+int nat_pmp_start(void)
+{
+    struct sockaddr_in addr = {
+        .sin_family = AF_INET,
+        .sin_port = htons(NAT_PMP_PORT),
+    };
+
+    if(pmp_sock >= 0)
+    {
+        return 0;
+    }
+
+    if(zsock_inet_pton(AF_INET, CONFIG_WIFI_SAMPLE_AP_IP_ADDRESS, &addr.sin_addr) != 1)
+    {
+        LOG_ERR("NAT-PMP: Invalid AP address");
+        return -EINVAL;
+    }
+
+    pmp_sock = zsock_socket(AF_INET, SOCK_DGRAM, IPPROTO_UDP);
+    if(pmp_sock < 0)
+    {
+        LOG_ERR("NAT-PMP: socket failed (%d)", errno);
+        return -errno;
+    }
+
+    if(zsock_bind(pmp_sock, (struct sockaddr *)&addr, sizeof(addr)) < 0)
+    {
+        LOG_ERR("NAT-PMP: bind failed (%d)", errno);
+        zsock_close(pmp_sock);
+        pmp_sock = -1;
+        return -errno;
+    }
+
+    k_thread_create(&pmp_thread, pmp_stack, K_THREAD_STACK_SIZEOF(pmp_stack),
+                    pmp_thread_fn, NULL, NULL, NULL,
+                    NAT_PMP_PRIORITY, 0, K_NO_WAIT);
+    k_thread_name_set(&pmp_thread, "nat_pmp");
+
+    LOG_INF("NAT-PMP/PCP server listening on %s:%d",
+            CONFIG_WIFI_SAMPLE_AP_IP_ADDRESS, NAT_PMP_PORT);
+    return 0;
+}
+
+#endif /* CONFIG_NET_IPV4_NAT_PMP */
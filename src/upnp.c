@@ -0,0 +1,279 @@
+// Copyright (c) 2025
+// SPDX-License-Identifier: Apache-2.0
+// Coskun ERGAN <coskunergan@gmail.com>
+
+#include <zephyr/kernel.h>
+#include <zephyr/logging/log.h>
+#include <zephyr/net/socket.h>
+#include <string.h>
+#include <errno.h>
+
+LOG_MODULE_DECLARE(esp32_wifi, LOG_LEVEL_DBG);
+
+#if defined(CONFIG_NET_IPV4_NAT_UPNP)
+
+#define SSDP_PORT 1900
+#define SSDP_MCAST_ADDR "239.255.255.250"
+#define SSDP_NOTIFY_INTERVAL_S 900
+#define UPNP_SSDP_BUF 512
+#define UPNP_HTTP_REQ_BUF 2048
+#define UPNP_HTTP_RESP_BUF 6144
+#define UPNP_HTTP_TIMEOUT_S 2
+#define UPNP_STACK_SIZE 3072
+#define UPNP_PRIORITY 7
+
+extern int nat_upnp_ssdp(const uint8_t *, size_t, const uint8_t *, uint8_t *, size_t);
+extern int nat_upnp_notify(size_t, const uint8_t *, uint8_t *, size_t);
+extern int nat_upnp_http(const uint8_t *, size_t, size_t, const uint8_t *, const uint8_t *, uint8_t *,
+                         size_t);
+
+static int ssdp_sock = -1;
+static int http_sock = -1;
+static struct in_addr host_addr;
+
+static uint8_t ssdp_buf[UPNP_SSDP_BUF];
+static uint8_t ssdp_resp[UPNP_SSDP_BUF];
+static uint8_t http_req[UPNP_HTTP_REQ_BUF];
+static uint8_t http_resp[UPNP_HTTP_RESP_BUF];
+
+static void ssdp_notify_handler(struct k_work *work);
+static K_WORK_DELAYABLE_DEFINE(notify_work, ssdp_notify_handler);
+
+K_THREAD_STACK_DEFINE(upnp_stack, UPNP_STACK_SIZE);
+static struct k_thread upnp_thread;
+
+static void ssdp_notify_handler(struct k_work *work)
+{
+    ARG_UNUSED(work);
+
+    uint8_t buf[UPNP_SSDP_BUF];
+    struct sockaddr_in dst = {
+        .sin_family = AF_INET,
+        .sin_port = htons(SSDP_PORT),
+    };
+
+    zsock_inet_pton(AF_INET, SSDP_MCAST_ADDR, &dst.sin_addr);
+
+    for(size_t i = 0;; i++)
+    {
+        int len = nat_upnp_notify(i, (const uint8_t *)&host_addr, buf, sizeof(buf));
+        if(len < 0)
+        {
+            break;
+        }
+        zsock_sendto(ssdp_sock, buf, len, 0, (struct sockaddr *)&dst, sizeof(dst));
+    }
+
+    k_work_reschedule(&notify_work, K_SECONDS(SSDP_NOTIFY_INTERVAL_S));
+}
+
+static void ssdp_receive(void)
+{
+    struct sockaddr_in client;
+    socklen_t client_len = sizeof(client);
+
+    int len = zsock_recvfrom(ssdp_sock, ssdp_buf, sizeof(ssdp_buf), 0,
+                             (struct sockaddr *)&client, &client_len);
+    if(len <= 0)
+    {
+        return;
+    }
+
+    int resp_len = nat_upnp_ssdp(ssdp_buf, len, (const uint8_t *)&host_addr,
+                                 ssdp_resp, sizeof(ssdp_resp));
+    if(resp_len > 0)
+    {
+        zsock_sendto(ssdp_sock, ssdp_resp, resp_len, 0,
+                     (struct sockaddr *)&client, client_len);
+    }
+}
+
+/* One request per connection, answered and closed */
+static void http_serve(void)
+{
+    struct sockaddr_in client;
+    socklen_t client_len = sizeof(client);
+    struct timeval timeout = {
+        .tv_sec = UPNP_HTTP_TIMEOUT_S,
+    };
+    size_t received = 0;
+
+    int conn = zsock_accept(http_sock, (struct sockaddr *)&client, &client_len);
+    if(conn < 0)
+    {
+        return;
+    }
+
+    zsock_setsockopt(conn, SOL_SOCKET, SO_RCVTIMEO, &timeout, sizeof(timeout));
+
+    while(received < sizeof(http_req))
+    {
+        int len = zsock_recv(conn, http_req + received, sizeof(http_req) - received, 0);
+        if(len <= 0)
+        {
+            break;
+        }
+        received += len;
+
+        int resp_len = nat_upnp_http(http_req, received, sizeof(http_req),
+                                     (const uint8_t *)&client.sin_addr, (const uint8_t *)&host_addr,
+                                     http_resp, sizeof(http_resp));
+        if(resp_len < 0)
+        {
+            break;
+        }
+        if(resp_len > 0)
+        {
+            size_t sent = 0;
+            while(sent < (size_t)resp_len)
+            {
+                int n = zsock_send(conn, http_resp + sent, resp_len - sent, 0);
+                if(n <= 0)
+                {
+                    break;
+                }
+                sent += n;
+            }
+            break;
+        }
+    }
+
+    zsock_close(conn);
+}
+
+static void upnp_thread_fn(void *p1, void *p2, void *p3)
+{
+    ARG_UNUSED(p1);
+    ARG_UNUSED(p2);
+    ARG_UNUSED(p3);
+
+    struct zsock_pollfd fds[2] = {
+        { .fd = ssdp_sock, .events = ZSOCK_POLLIN },
+        { .fd = http_sock, .events = ZSOCK_POLLIN },
+    };
+
+    while(1)
+    {
+        if(zsock_poll(fds, ARRAY_SIZE(fds), -1) < 0)
+        {
+            LOG_ERR("UPnP: poll failed (%d)", errno);
+            k_sleep(K_MSEC(100));
+            continue;
+        }
+
+        if(fds[0].revents & ZSOCK_POLLIN)
+        {
+            ssdp_receive();
+        }
+
+        if(fds[1].revents & ZSOCK_POLLIN)
+        {
+            http_serve();
+        }
+    }
+}
+
+static int ssdp_open(void)
+{
+    struct sockaddr_in addr = {
+        .sin_family = AF_INET,
+        .sin_port = htons(SSDP_PORT),
+        .sin_addr.s_addr = htonl(INADDR_ANY),
+    };
+    struct ip_mreqn mreq = {
+        .imr_address = host_addr,
+    };
+    int opt = 1;
+
+    zsock_inet_pton(AF_INET, SSDP_MCAST_ADDR, &mreq.imr_multiaddr);
+
+    ssdp_sock = zsock_socket(AF_INET, SOCK_DGRAM, IPPROTO_UDP);
+    if(ssdp_sock < 0)
+    {
+        LOG_ERR("UPnP: SSDP socket failed (%d)", errno);
+        return -errno;
+    }
+
+    zsock_setsockopt(ssdp_sock, SOL_SOCKET, SO_REUSEADDR, &opt, sizeof(opt));
+
+    if(zsock_bind(ssdp_sock, (struct sockaddr *)&addr, sizeof(addr)) < 0 ||
+       zsock_setsockopt(ssdp_sock, IPPROTO_IP, IP_ADD_MEMBERSHIP, &mreq, sizeof(mreq)) < 0)
+    {
+        LOG_ERR("UPnP: SSDP setup failed (%d)", errno);
+        zsock_close(ssdp_sock);
+        ssdp_sock = -1;
+        return -errno;
+    }
+
+    return 0;
+}
+
+static int http_open(void)
+{
+    struct sockaddr_in addr = {
+        .sin_family = AF_INET,
+        .sin_port = htons(CONFIG_NET_IPV4_NAT_UPNP_PORT),
+        .sin_addr = host_addr,
+    };
+
+    http_sock = zsock_socket(AF_INET, SOCK_STREAM, IPPROTO_TCP);
+    if(http_sock < 0)
+    {
+        LOG_ERR("UPnP: HTTP socket failed (%d)", errno);
+        return -errno;
+    }
+
+    if(zsock_bind(http_sock, (struct sockaddr *)&addr, sizeof(addr)) < 0 ||
+       zsock_listen(http_sock, 2) < 0)
+    {
+        LOG_ERR("UPnP: HTTP setup failed (%d)", errno);
+        zsock_close(http_sock);
+        http_sock = -1;
+        return -errno;
+    }
+
+    return 0;
+}
+
+int nat_upnp_start(void)
+{
+    int ret;
+
+    if(ssdp_sock >= 0)
+    {
+        return 0;
+    }
+
+    if(zsock_inet_pton(AF_INET, CONFIG_WIFI_SAMPLE_AP_IP_ADDRESS, &host_addr) != 1)
+    {
+        LOG_ERR("UPnP: Invalid AP address");
+        return -EINVAL;
+    }
+
+    ret = ssdp_open();
+    if(ret < 0)
+    {
+        return ret;
+    }
+
+    ret = http_open();
+    if(ret < 0)
+    {
+        zsock_close(ssdp_sock);
+        ssdp_sock = -1;
+        return ret;
+    }
+
+    k_thread_create(&upnp_thread, upnp_stack, K_THREAD_STACK_SIZEOF(upnp_stack),
+                    upnp_thread_fn, NULL, NULL, NULL,
+                    UPNP_PRIORITY, 0, K_NO_WAIT);
+    k_thread_name_set(&upnp_thread, "nat_upnp");
+
+    k_work_reschedule(&notify_work, K_NO_WAIT);
+
+    LOG_INF("UPnP IGD listening on %s:%d", CONFIG_WIFI_SAMPLE_AP_IP_ADDRESS,
+            CONFIG_NET_IPV4_NAT_UPNP_PORT);
+    return 0;
+}
+
+#endif /* CONFIG_NET_IPV4_NAT_UPNP */
@@ -38,6 +38,12 @@ extern const uint8_t *get_current_ssid(void);
 extern uint8_t get_current_psk_len(void);
 extern const uint8_t *get_current_psk(void);
 extern int nat_configure(const uint8_t *, const uint8_t *, const uint8_t *, struct net_if *, struct net_if *);
+#if defined(CONFIG_NET_IPV4_NAT_PMP)
+extern int nat_pmp_start(void);
+#endif
+#if defined(CONFIG_NET_IPV4_NAT_UPNP)
+extern int nat_upnp_start(void);
+#endif
 
 #if CONFIG_NET_DHCPV4_SERVER
 static void enable_dhcpv4_server(void)
@@ -372,5 +378,19 @@ void wifi_connect(void)
     {
         LOG_INF("NAT configured: AP=%p STA=%p", ap_iface, sta_iface);
     }
+
+#if defined(CONFIG_NET_IPV4_NAT_PMP)
+    if(nat_pmp_start() < 0)
+    {
+        LOG_ERR("Failed to start NAT-PMP/PCP server");
+    }
+#endif
+
+#if defined(CONFIG_NET_IPV4_NAT_UPNP)
+    if(nat_upnp_start() < 0)
+    {
+        LOG_ERR("Failed to start UPnP IGD service");
+    }
+#endif
 #endif
 }
\ No newline at end of file